@@ -1,7 +1,10 @@
 use crate::backends::BuildProfile;
-use clap::{Args, Parser, Subcommand};
+use crate::package::management::FetchPolicy;
+use clap::{ArgGroup, Args, Parser, Subcommand};
 use serde_derive::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::str::FromStr;
+use url::Url;
 
 #[derive(clap::ValueEnum, Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, Hash)]
 #[value(rename_all = "lowercase")]
@@ -65,13 +68,36 @@ pub struct BuildArgs {
     #[arg(short, long)]
     pub release: bool,
 
-    /// List of apps to build if left empty all apps are built
+    /// List of apps to build if left empty all apps are built, accepts names and globs like `Timer*`
     #[arg(short, long, value_delimiter = ',')]
     pub apps: Vec<String>,
 
+    /// Builds the apps whose name matches this regex as well
+    #[arg(long)]
+    pub regex: Vec<String>,
+
+    /// Apps that are not built, accepts names and globs
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Only builds apps that have one of these tags
+    #[arg(long = "tag", value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Only builds the N-th of M equally sized parts of the selected apps, e.g. 2/4
+    #[arg(long)]
+    pub shard: Option<Shard>,
+
+    /// Only builds the apps that failed in the last build, run or test
+    #[arg(long)]
+    pub failed: bool,
+
     /// Number of threads to use for parallel builds. Zero means it will be determined automatically.
     #[arg(short, long, default_value_t = 0)]
     pub threads: usize,
+
+    #[command(flatten)]
+    pub lock: LockArgs,
 }
 
 impl BuildArgs {
@@ -84,6 +110,61 @@ impl BuildArgs {
     }
 }
 
+/// part of the selected apps that is built, e.g. by one of several CI machines
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shard {
+    /// starts at one
+    pub index: usize,
+    pub count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected N/M with 1 <= N <= M, got {s}");
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index = index.parse::<usize>().map_err(|_| invalid())?;
+        let count = count.parse::<usize>().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub build: BuildArgs,
+
+    /// Overwrites the expected output files with the output of the apps
+    #[arg(long)]
+    pub bless: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct TestArgs {
+    #[command(flatten)]
+    pub build: BuildArgs,
+
+    /// Overwrites the expected output files with the output of the tests
+    #[arg(long)]
+    pub bless: bool,
+
+    /// Seconds after which a running test is killed and counted as failed
+    #[arg(long, default_value_t = 60)]
+    pub timeout: u64,
+
+    /// Writes a JUnit XML report of the test results into this file
+    #[arg(long)]
+    pub junit: Option<PathBuf>,
+
+    /// Runs the apps as tests as well, e.g. for test suites written as [[app]] entries
+    #[arg(long)]
+    pub include_apps: bool,
+}
+
 #[derive(Args, Debug)]
 pub struct InitArgs {
     #[arg(value_enum, short, long)]
@@ -106,6 +187,155 @@ impl InitArgs {
     }
 }
 
+#[derive(Args, Debug)]
+pub struct UpdateArgs {
+    /// Dependencies to update, all other dependencies stay pinned to Lingo.lock.
+    /// If left empty all dependencies are updated
+    pub packages: Vec<String>,
+
+    #[command(flatten)]
+    pub lock: LockArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct LockArgs {
+    /// Fails if Lingo.lock is missing or would need to be changed
+    #[arg(long)]
+    pub locked: bool,
+
+    /// Fails instead of accessing the network, only cached and local dependencies are used
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Same as --locked and --offline together
+    #[arg(long)]
+    pub frozen: bool,
+
+    /// Only warns if a dependency does not match the checksum in Lingo.lock
+    #[arg(long)]
+    pub ignore_checksums: bool,
+}
+
+impl LockArgs {
+    pub fn fetch_policy(&self) -> FetchPolicy {
+        FetchPolicy {
+            locked: self.locked || self.frozen,
+            offline: self.offline || self.frozen,
+            ignore_checksums: self.ignore_checksums,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("source").args(["git", "path", "tarball", "registry"])))]
+#[command(group(ArgGroup::new("git_lock").args(["tag", "branch", "rev"])))]
+pub struct AddArgs {
+    /// Name of the dependency
+    pub name: String,
+
+    /// Git repository the dependency is cloned from
+    #[arg(long)]
+    pub git: Option<Url>,
+
+    /// Local directory containing the dependency
+    #[arg(long)]
+    pub path: Option<PathBuf>,
+
+    /// Url of a tarball containing the dependency
+    #[arg(long)]
+    pub tarball: Option<Url>,
+
+    /// Location of the registry index, if no source is given the registry
+    /// configured in the environment is used
+    #[arg(long)]
+    pub registry: Option<String>,
+
+    /// Git tag to check out
+    #[arg(long)]
+    pub tag: Option<String>,
+
+    /// Git branch to check out
+    #[arg(long)]
+    pub branch: Option<String>,
+
+    /// Git revision to check out
+    #[arg(long)]
+    pub rev: Option<String>,
+
+    /// Expected sha256 of the tarball
+    #[arg(long)]
+    pub sha256: Option<String>,
+
+    /// Version requirement of the dependency, defaults to the resolved version
+    #[arg(long)]
+    pub version: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct RemoveArgs {
+    /// Name of the dependency
+    pub name: String,
+}
+
+#[derive(Args, Debug)]
+pub struct TreeArgs {
+    /// Shows the packages that depend on the given package instead
+    #[arg(short, long)]
+    pub invert: Option<String>,
+
+    /// Shows only packages that are required with different version requirements
+    #[arg(short, long)]
+    pub duplicates: bool,
+
+    /// Maximum depth of the printed tree
+    #[arg(long)]
+    pub depth: Option<usize>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+#[value(rename_all = "lowercase")]
+pub enum SbomFormat {
+    CycloneDx,
+    Spdx,
+}
+
+#[derive(Args, Debug)]
+pub struct SbomArgs {
+    /// Format of the bill of materials
+    #[arg(long, value_enum, default_value_t = SbomFormat::CycloneDx)]
+    pub format: SbomFormat,
+
+    /// Writes the bill of materials into this file instead of printing it
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// lists the cached packages
+    List,
+
+    /// removes all cached packages
+    Clean,
+
+    /// removes cached packages that have not been used recently
+    Gc(GcArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct GcArgs {
+    /// Removes packages that have not been used for this many days
+    #[arg(long, default_value_t = 30)]
+    pub max_age: u64,
+}
+
+#[allow(clippy::large_enum_variant)] // parsed once at startup, boxing would only add noise
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// initializing a lingua-franca project
@@ -115,13 +345,34 @@ pub enum Command {
     Build(BuildArgs),
 
     /// Updates the dependencies and potentially build tools
-    Update,
+    Update(UpdateArgs),
+
+    /// adds a dependency to Lingo.toml
+    Add(AddArgs),
+
+    /// removes a dependency from Lingo.toml
+    Remove(RemoveArgs),
+
+    /// prints the resolved dependency tree
+    Tree(TreeArgs),
 
     /// builds and runs binaries
-    Run(BuildArgs),
+    Run(RunArgs),
+
+    /// builds and runs the tests together with the dev-dependencies
+    Test(TestArgs),
 
     /// removes build artifacts
     Clean,
+
+    /// manages the shared download cache
+    Cache(CacheArgs),
+
+    /// lists the dependencies grouped by their license
+    Licenses,
+
+    /// writes a software bill of materials of all dependencies
+    Sbom(SbomArgs),
 }
 
 #[derive(Parser)]
@@ -2,6 +2,7 @@ use std::fs;
 use std::io::Write;
 use std::process::Command;
 
+use crate::backends::cmake_lib::{self, LfcVersion};
 use crate::backends::{
     BatchBackend, BatchBuildResults, BuildCommandOptions, BuildProfile, BuildResult, CommandSpec,
 };
@@ -11,7 +12,11 @@ use crate::util::execute_command_to_build_result;
 
 pub struct CmakeC;
 
-fn gen_cmake_files(app: &App, options: &BuildCommandOptions) -> BuildResult {
+fn gen_cmake_files(
+    app: &App,
+    lfc_version: &LfcVersion,
+    options: &BuildCommandOptions,
+) -> BuildResult {
     let build_dir = app.output_root.join("build");
     fs::create_dir_all(&build_dir)?;
 
@@ -21,8 +26,9 @@ fn gen_cmake_files(app: &App, options: &BuildCommandOptions) -> BuildResult {
     let cmake_file = app_build_folder.clone().join("CMakeLists.txt");
 
     // create potential files that come from the target properties
+    let variant = cmake_lib::variant(app, lfc_version)?;
     app.properties
-        .write_artifacts(&app_build_folder)
+        .write_artifacts(&app_build_folder, &variant)
         .expect("cannot write artifacts");
 
     // read file and append cmake include to generated cmake file
@@ -98,10 +104,16 @@ fn do_cmake_build(results: &mut BatchBuildResults, options: &BuildCommandOptions
     if !options.compile_target_code {
         return;
     }
+    let lfc_version = LfcVersion::new(options);
     results
+        // the first app of a variant builds the libraries, the other apps reuse them
+        .step("precompile libraries")
+        .map(|app| cmake_lib::build_precompiled(app, &lfc_version, options))
         // generate all CMake files ahead of time
-        .map(|app| gen_cmake_files(app, options))
+        .step("generate cmake files")
+        .map(|app| gen_cmake_files(app, &lfc_version, options))
         // Run cmake to build everything.
+        .step("compile")
         .map(|app| {
             let app_build_folder = app.src_gen_dir().join(&app.main_reactor_name);
 
@@ -120,6 +132,7 @@ fn do_cmake_build(results: &mut BatchBuildResults, options: &BuildCommandOptions
 
             execute_command_to_build_result(cmake)
         })
+        .step("install")
         .map(|app| {
             let bin_source = app
                 .src_gen_dir()
@@ -127,6 +140,11 @@ fn do_cmake_build(results: &mut BatchBuildResults, options: &BuildCommandOptions
                 .join(&app.main_reactor_name);
             fs::rename(bin_source, app.executable_path())?;
             Ok(())
+        })
+        .map(|app| {
+            app.properties
+                .install_artifacts(&app.output_root.join("bin"))?;
+            Ok(())
         });
 }
 
@@ -135,7 +153,7 @@ impl BatchBackend for CmakeC {
         match command {
             CommandSpec::Build(options) => do_cmake_build(results, options),
             CommandSpec::Clean => {
-                results.par_map(|app| {
+                results.step("clean").par_map(|app| {
                     crate::util::default_build_clean(&app.output_root)?;
                     Ok(())
                 });
@@ -6,13 +6,18 @@ use std::process::Command;
 use crate::package::App;
 use crate::util::execute_command_to_build_result;
 
+use crate::backends::cmake_lib::{self, LfcVersion};
 use crate::backends::{
     BatchBackend, BatchBuildResults, BuildCommandOptions, BuildProfile, BuildResult, CommandSpec,
 };
 
 pub struct CmakeCpp;
 
-fn gen_cmake_files(app: &App, options: &BuildCommandOptions) -> BuildResult {
+fn gen_cmake_files(
+    app: &App,
+    lfc_version: &LfcVersion,
+    options: &BuildCommandOptions,
+) -> BuildResult {
     let build_dir = app.output_root.join("build");
     fs::create_dir_all(&build_dir)?;
 
@@ -21,7 +26,9 @@ fn gen_cmake_files(app: &App, options: &BuildCommandOptions) -> BuildResult {
     let cmake_file = app_build_folder.clone().join("CMakeLists.txt");
 
     // create potential files that come from the target properties
-    app.properties.write_artifacts(&app_build_folder)?;
+    let variant = cmake_lib::variant(app, lfc_version)?;
+    app.properties
+        .write_artifacts(&app_build_folder, &variant)?;
 
     // we need to modify the cmake file here to include our generated cmake files
     let src_gen_dir = app.src_gen_dir();
@@ -81,11 +88,17 @@ fn do_cmake_build(results: &mut BatchBuildResults, options: &BuildCommandOptions
     if !options.compile_target_code {
         return;
     }
+    let lfc_version = LfcVersion::new(options);
 
     results
+        // the first app of a variant builds the libraries, the other apps reuse them
+        .step("precompile libraries")
+        .map(|app| cmake_lib::build_precompiled(app, &lfc_version, options))
         // generate all CMake files ahead of time
-        .map(|app| gen_cmake_files(app, options))
+        .step("generate cmake files")
+        .map(|app| gen_cmake_files(app, &lfc_version, options))
         // Run cmake to build everything.
+        .step("compile")
         .gather(|apps| {
             let build_dir = apps[0].output_root.join("build");
 
@@ -102,6 +115,7 @@ fn do_cmake_build(results: &mut BatchBuildResults, options: &BuildCommandOptions
             // note: by parsing CMake stderr we would know which specific targets have failed.
             execute_command_to_build_result(cmake)
         })
+        .step("install")
         .map(|app| {
             let build_dir = app.output_root.join("build");
             // installing
@@ -115,6 +129,7 @@ fn do_cmake_build(results: &mut BatchBuildResults, options: &BuildCommandOptions
             // cleanup: rename executable to match the app name
             let bin_dir = app.output_root.join("bin");
             fs::rename(bin_dir.join(cmake_binary_name), app.executable_path())?;
+            app.properties.install_artifacts(&bin_dir)?;
             Ok(())
         });
 }
@@ -124,7 +139,7 @@ impl BatchBackend for CmakeCpp {
         match command {
             CommandSpec::Build(options) => do_cmake_build(results, options),
             CommandSpec::Clean => {
-                results.par_map(|app| {
+                results.step("clean").par_map(|app| {
                     crate::util::default_build_clean(&app.output_root)?;
                     Ok(())
                 });
@@ -0,0 +1,474 @@
+use std::cell::OnceCell;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{fs, io};
+
+use sha2::{Digest, Sha256};
+
+use crate::args::TargetLanguage;
+use crate::backends::{BuildCommandOptions, BuildProfile};
+use crate::package::lock::DependencyLock;
+use crate::package::target_properties::{
+    LibraryTargetProperties, MergeTargetProperties, PrecompiledLibrary,
+};
+use crate::package::tree::DependencyTreeNode;
+use crate::package::App;
+use crate::util::checksum;
+use crate::util::errors::{AnyError, BuildResult};
+use crate::util::execute_command_to_build_result;
+
+/// directory inside the build folder that contains the precompiled libraries
+pub const PRECOMPILED_DIRECTORY: &str = "precompiled";
+
+/// Aggregates the target properties of all libraries. Libraries with native sources are
+/// compiled into a static library once, apps link against it instead of compiling the sources
+/// themselves. The libraries are built by [`build_precompiled`] once lfc generated the runtime.
+pub fn precompiled_properties(
+    lock: &DependencyLock,
+    output_root: &Path,
+    options: &BuildCommandOptions,
+) -> anyhow::Result<LibraryTargetProperties> {
+    let mut aggregated = LibraryTargetProperties::default();
+    for library in lock.libraries() {
+        let mut properties = library.properties.resolve_paths(&library.location);
+        if !properties.sources.is_empty() {
+            // the cmake-include is applied to the library, the apps get what it adds to the
+            // library through the imported target
+            let cmake_include = std::mem::take(&mut properties.cmake_include);
+            properties.precompiled = vec![precompiled(
+                library,
+                std::mem::take(&mut properties.sources),
+                cmake_include.to_string(),
+                &output_root.join(PRECOMPILED_DIRECTORY),
+                options,
+            )];
+        }
+        aggregated.merge(&properties)?;
+    }
+
+    Ok(aggregated)
+}
+
+/// where a library is built, a build of the same checksum and profile is reused
+fn precompiled(
+    library: &DependencyTreeNode,
+    sources: Vec<PathBuf>,
+    cmake_include: String,
+    precompiled_root: &Path,
+    options: &BuildCommandOptions,
+) -> PrecompiledLibrary {
+    // the enabled features can add sources, so every feature set is built separately
+    let mut key = library.hash.clone();
+    if !library.features.is_empty() {
+        key = format!("{key}-{}", library.features.join("+"));
+    }
+    PrecompiledLibrary {
+        name: library.name.clone(),
+        directory: precompiled_root.join(format!("{key}-{}", profile(options).to_lowercase())),
+        sources,
+        cmake_include,
+    }
+}
+
+fn profile(options: &BuildCommandOptions) -> &'static str {
+    match options.profile {
+        BuildProfile::Release => "Release",
+        BuildProfile::Debug => "Debug",
+    }
+}
+
+/// The version of lfc, it is only asked for once and only if an app links against precompiled
+/// libraries. The error is kept as message, because it is reported for every app.
+pub struct LfcVersion<'a> {
+    lfc: &'a Path,
+    version: OnceCell<Result<String, String>>,
+}
+
+impl<'a> LfcVersion<'a> {
+    pub fn new(options: &'a BuildCommandOptions) -> Self {
+        Self {
+            lfc: &options.lfc_exec_path,
+            version: OnceCell::new(),
+        }
+    }
+
+    fn get(&self) -> Result<&str, &str> {
+        self.version
+            .get_or_init(|| {
+                let output = Command::new(self.lfc)
+                    .arg("--version")
+                    .output()
+                    .map_err(|e| format!("cannot run {}: {e}", self.lfc.display()))?;
+                if !output.status.success() {
+                    return Err(format!(
+                        "cannot determine the version of {}: {}",
+                        self.lfc.display(),
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+            })
+            .as_ref()
+            .map(String::as_str)
+            .map_err(String::as_str)
+    }
+}
+
+/// what lfc generated for an app that determines the ABI of the libraries compiled for it
+struct Runtime {
+    /// directories containing the headers of the runtime
+    include_directories: Vec<PathBuf>,
+    /// the statements of the CMake file of the app that define preprocessor symbols
+    compile_definitions: Vec<String>,
+}
+
+impl Runtime {
+    fn of(app: &App) -> io::Result<Self> {
+        let src_gen = app.src_gen_dir();
+        let cmake_lists = src_gen.join(&app.main_reactor_name).join("CMakeLists.txt");
+        let compile_definitions = match fs::read_to_string(cmake_lists) {
+            Ok(content) => compile_definitions(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            include_directories: runtime_include_directories(&src_gen)?,
+            compile_definitions,
+        })
+    }
+}
+
+/// The directory below the one of a precompiled library that holds its build for the app, it
+/// is empty if the app does not link against precompiled libraries. Apps share a build if they
+/// have the same target, lfc version, runtime headers and compile definitions.
+pub fn variant(app: &App, lfc_version: &LfcVersion) -> Result<String, Box<AnyError>> {
+    if app.properties.precompiled().is_empty() {
+        return Ok(String::new());
+    }
+    Ok(variant_of(
+        app.target,
+        lfc_version.get()?,
+        &Runtime::of(app)?,
+    )?)
+}
+
+fn variant_of(target: TargetLanguage, lfc_version: &str, runtime: &Runtime) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut update = |value: &[u8]| {
+        hasher.update((value.len() as u64).to_le_bytes());
+        hasher.update(value);
+    };
+    update(lfc_version.as_bytes());
+    for definition in &runtime.compile_definitions {
+        update(definition.as_bytes());
+    }
+    // the headers are hashed relative to their include directory, because every app has its
+    // own copy of the runtime
+    for directory in &runtime.include_directories {
+        let mut headers = fs::read_dir(directory)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        headers.retain(|path| path.is_file());
+        headers.sort();
+        for header in headers {
+            update(header.file_name().unwrap_or_default().as_encoded_bytes());
+            update(&fs::read(&header)?);
+        }
+        update(b"");
+    }
+    let hash = checksum::hex(&hasher.finalize());
+    Ok(format!(
+        "{}-{}",
+        format!("{target:?}").to_lowercase(),
+        &hash[..16]
+    ))
+}
+
+/// only apps of the C++ targets compile the libraries with a C++ compiler
+fn languages(target: TargetLanguage) -> &'static str {
+    match target {
+        TargetLanguage::CCpp | TargetLanguage::Cpp => "C CXX",
+        _ => "C",
+    }
+}
+
+/// Builds the libraries the app links against, unless they were built for the same variant
+/// already. The sources are compiled against the headers of the runtime that lfc generated for
+/// the app and with its compile definitions.
+pub fn build_precompiled(
+    app: &App,
+    lfc_version: &LfcVersion,
+    options: &BuildCommandOptions,
+) -> BuildResult {
+    if app.properties.precompiled().is_empty() {
+        return Ok(());
+    }
+    let runtime = Runtime::of(app)?;
+    let variant = variant_of(app.target, lfc_version.get()?, &runtime)?;
+    for library in app.properties.precompiled() {
+        build(
+            library,
+            &library.config_directory(&variant),
+            languages(app.target),
+            &runtime,
+            options,
+        )?;
+    }
+    Ok(())
+}
+
+fn build(
+    library: &PrecompiledLibrary,
+    directory: &Path,
+    languages: &str,
+    runtime: &Runtime,
+    options: &BuildCommandOptions,
+) -> BuildResult {
+    let profile = profile(options);
+
+    // the package config is written last, so it only exists for complete builds
+    let config_file = directory.join(format!("{}Config.cmake", library.name));
+    if config_file.exists() {
+        return Ok(());
+    }
+
+    log::info!("Precompiling library {}", library.name);
+    fs::create_dir_all(directory)?;
+    fs::write(
+        directory.join("CMakeLists.txt"),
+        cmake_lists(library, languages, runtime),
+    )?;
+
+    let build_directory = directory.join("build");
+    let mut cmake = Command::new("cmake");
+    cmake.arg(format!("-DCMAKE_BUILD_TYPE={profile}"));
+    cmake.arg("-S").arg(directory);
+    cmake.arg("-B").arg(&build_directory);
+    execute_command_to_build_result(cmake)?;
+
+    let mut cmake = Command::new("cmake");
+    cmake.arg("--build").arg(&build_directory);
+    cmake.args(["--config", profile]);
+    if options.max_threads != 0 {
+        cmake.args(["--parallel", &options.max_threads.to_string()]);
+    }
+    execute_command_to_build_result(cmake)?;
+
+    fs::write(&config_file, package_config(&library.name))?;
+    Ok(())
+}
+
+/// the `include` directories lfc generates next to the code of the app contain the headers of
+/// the runtime, the runtime includes its headers relative to every directory inside of them
+fn runtime_include_directories(src_gen: &Path) -> io::Result<Vec<PathBuf>> {
+    fn collect(directory: &Path, inside_include: bool, found: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let is_include =
+                inside_include || path.file_name().is_some_and(|name| name == "include");
+            if is_include {
+                found.push(path.clone());
+            }
+            collect(&path, is_include, found)?;
+        }
+        Ok(())
+    }
+
+    let mut found = vec![];
+    if src_gen.is_dir() {
+        collect(src_gen, false, &mut found)?;
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// The statements that define preprocessor symbols for the target of the app or for all
+/// targets, e.g. `target_compile_definitions(${LF_MAIN_TARGET} PUBLIC NUMBER_OF_WORKERS=4)`.
+fn compile_definitions(cmake_lists: &str) -> Vec<String> {
+    let mut definitions = vec![];
+    let mut rest = cmake_lists;
+    while let Some(start) = ["target_compile_definitions(", "add_compile_definitions("]
+        .iter()
+        .filter_map(|command| rest.find(command))
+        .min()
+    {
+        let statement = &rest[start..];
+        let mut depth = 0;
+        let end = statement.find(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth == 0 && c == ')'
+        });
+        let Some(end) = end else {
+            break;
+        };
+        let statement = &statement[..=end];
+        let applies_to_app = !statement.starts_with("target_")
+            || statement["target_compile_definitions(".len()..]
+                .trim_start()
+                .starts_with("${LF_MAIN_TARGET}");
+        if applies_to_app {
+            definitions.push(statement.to_string());
+        }
+        rest = &rest[start + end + 1..];
+    }
+    definitions
+}
+
+/// the headers of a library are expected next to its sources
+fn include_directories(sources: &[PathBuf]) -> Vec<&Path> {
+    let mut directories = sources
+        .iter()
+        .filter_map(|source| source.parent())
+        .collect::<Vec<_>>();
+    directories.sort();
+    directories.dedup();
+    directories
+}
+
+fn quoted(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> String {
+    paths
+        .into_iter()
+        .map(|path| format!("\n    \"{}\"", path.as_ref().display()))
+        .collect()
+}
+
+fn cmake_lists(library: &PrecompiledLibrary, languages: &str, runtime: &Runtime) -> String {
+    format!(
+        r#"cmake_minimum_required(VERSION 3.13)
+project({name} LANGUAGES {languages})
+
+add_library({name} STATIC{sources}
+)
+target_include_directories({name} PUBLIC{includes}
+)
+target_include_directories({name} PRIVATE{runtime_includes}
+)
+
+# compile definitions of the app and cmake-include of the library, applied to the library
+# instead of the app
+set(LF_MAIN_TARGET {name})
+{definitions}
+{cmake_include}
+
+# the package config imports the library together with what the cmake-include added to it
+export(TARGETS {name} NAMESPACE lingo:: FILE "${{CMAKE_CURRENT_SOURCE_DIR}}/{name}Targets.cmake")
+"#,
+        name = library.name,
+        sources = quoted(&library.sources),
+        includes = quoted(include_directories(&library.sources)),
+        runtime_includes = quoted(&runtime.include_directories),
+        definitions = runtime.compile_definitions.join("\n"),
+        cmake_include = library.cmake_include,
+    )
+}
+
+fn package_config(name: &str) -> String {
+    format!(
+        r#"# precompiled library {name}, generated by lingo
+if(NOT TARGET lingo::{name})
+    include("${{CMAKE_CURRENT_LIST_DIR}}/{name}Targets.cmake")
+endif()
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_c_library_against_runtime() {
+        let src_gen = tempfile::tempdir().unwrap();
+        fs::create_dir_all(src_gen.path().join("Main/include/core/platform")).unwrap();
+        fs::create_dir_all(src_gen.path().join("Main/core")).unwrap();
+        let runtime_includes = runtime_include_directories(src_gen.path()).unwrap();
+        assert_eq!(
+            runtime_includes,
+            vec![
+                src_gen.path().join("Main/include"),
+                src_gen.path().join("Main/include/core"),
+                src_gen.path().join("Main/include/core/platform"),
+            ]
+        );
+
+        let library = PrecompiledLibrary {
+            name: "mqtt".to_string(),
+            directory: PathBuf::from("/precompiled/abc-debug"),
+            sources: vec![PathBuf::from("/mqtt/src/client.c")],
+            cmake_include: "target_link_libraries(${LF_MAIN_TARGET} PUBLIC m)".to_string(),
+        };
+        let runtime = Runtime {
+            include_directories: runtime_includes[..1].to_vec(),
+            compile_definitions: vec![
+                "target_compile_definitions(${LF_MAIN_TARGET} PUBLIC LOG_LEVEL=2)".to_string(),
+            ],
+        };
+        let lists = cmake_lists(&library, languages(TargetLanguage::C), &runtime);
+        assert!(lists.contains("project(mqtt LANGUAGES C)\n"));
+        assert!(lists.contains("target_include_directories(mqtt PUBLIC\n    \"/mqtt/src\"\n)"));
+        assert!(lists.contains(&format!(
+            "target_include_directories(mqtt PRIVATE\n    \"{}\"\n)",
+            runtime_includes[0].display()
+        )));
+        assert!(lists.contains(
+            "set(LF_MAIN_TARGET mqtt)\ntarget_compile_definitions(${LF_MAIN_TARGET} PUBLIC LOG_LEVEL=2)\ntarget_link_libraries(${LF_MAIN_TARGET} PUBLIC m)\n"
+        ));
+        assert!(lists.contains("export(TARGETS mqtt NAMESPACE lingo::"));
+    }
+
+    #[test]
+    fn extracts_the_compile_definitions_of_the_app() {
+        let cmake_lists = r#"
+add_executable(${LF_MAIN_TARGET} ${CoreLib}/main.c)
+target_compile_definitions(${LF_MAIN_TARGET} PUBLIC LOG_LEVEL=2)
+target_compile_definitions(reactor-c PUBLIC PLATFORM_Linux)
+add_compile_definitions(
+    NUMBER_OF_WORKERS=4
+    LF_SOURCE_DIRECTORY="$(dirname)"
+)
+"#;
+        assert_eq!(
+            compile_definitions(cmake_lists),
+            vec![
+                "target_compile_definitions(${LF_MAIN_TARGET} PUBLIC LOG_LEVEL=2)",
+                "add_compile_definitions(\n    NUMBER_OF_WORKERS=4\n    LF_SOURCE_DIRECTORY=\"$(dirname)\"\n)",
+            ]
+        );
+    }
+
+    #[test]
+    fn builds_separately_for_every_target_lfc_and_runtime() {
+        let include = tempfile::tempdir().unwrap();
+        fs::write(include.path().join("reactor.h"), "struct reactor {};").unwrap();
+        let runtime = || Runtime {
+            include_directories: vec![include.path().to_path_buf()],
+            compile_definitions: vec![],
+        };
+        let variant = |target, lfc, runtime: &Runtime| variant_of(target, lfc, runtime).unwrap();
+
+        let c = variant(TargetLanguage::C, "lfc 0.8.0", &runtime());
+        assert!(c.starts_with("c-"), "{c}");
+        assert_eq!(c, variant(TargetLanguage::C, "lfc 0.8.0", &runtime()));
+        assert!(variant(TargetLanguage::Cpp, "lfc 0.8.0", &runtime()).starts_with("cpp-"));
+        assert_ne!(c, variant(TargetLanguage::C, "lfc 0.9.0", &runtime()));
+
+        let mut threaded = runtime();
+        threaded.compile_definitions =
+            vec!["add_compile_definitions(NUMBER_OF_WORKERS=4)".to_string()];
+        assert_ne!(c, variant(TargetLanguage::C, "lfc 0.8.0", &threaded));
+
+        fs::write(
+            include.path().join("reactor.h"),
+            "struct reactor { int a; };",
+        )
+        .unwrap();
+        assert_ne!(c, variant(TargetLanguage::C, "lfc 0.8.0", &runtime()));
+    }
+}
@@ -21,7 +21,9 @@ impl LFC {
     ) {
         results.keep_going(options.keep_going);
         // TODO: using map_par introduced a race condition
-        results.map(|app| LFC::do_lfc_codegen(app, options, compile_target_code));
+        results
+            .step("generate code")
+            .map(|app| LFC::do_lfc_codegen(app, options, compile_target_code));
     }
 
     /// Do codegen for a single app.
@@ -47,9 +49,10 @@ impl BatchBackend for LFC {
             CommandSpec::Build(options) => {
                 LFC::do_parallel_lfc_codegen(options, results, options.compile_target_code)
             }
-            CommandSpec::Update => todo!(),
+            // dependencies are updated before the backends are invoked
+            CommandSpec::Update(_) => {}
             CommandSpec::Clean => {
-                results.par_map(|app| {
+                results.step("clean").par_map(|app| {
                     crate::util::default_build_clean(&app.output_root)?;
                     Ok(())
                 });
@@ -114,3 +117,35 @@ impl<'a> Display for LfcJsonArgs<'a> {
         write!(f, "{}", string)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::TargetLanguage;
+    use crate::package::AppFile;
+
+    #[test]
+    fn serializes_only_arguments_known_to_lfc() {
+        let app = AppFile {
+            name: None,
+            main: Some("src/Main.lf".into()),
+            target: TargetLanguage::C,
+            platform: None,
+            properties: Default::default(),
+            expect: None,
+            tags: vec![],
+        }
+        .convert("app", Path::new("/app"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&LfcJsonArgs::new(&app, false).to_string()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "src": "/app/src/Main.lf",
+                "out": "/app/build",
+                "properties": { "no-compile": true },
+            })
+        );
+    }
+}
@@ -7,14 +7,17 @@ use std::sync::Arc;
 
 use crate::args::{BuildSystem, Platform, TargetLanguage};
 use crate::package::{
-    management::DependencyManager, target_properties::MergeTargetProperties, App, Config,
-    OUTPUT_DIRECTORY,
+    management::{DependencyManager, FetchPolicy},
+    target_properties::MergeTargetProperties,
+    App, Config, OUTPUT_DIRECTORY,
 };
+use crate::util::build_report::{AppReport, BuildReport};
 use crate::util::errors::{AnyError, BuildResult, LingoError};
-use crate::{GitCloneAndCheckoutCap, WhichCapability};
+use crate::{DownloadCapability, GitCloneAndCheckoutCap, GitListTagsCapability, WhichCapability};
 
 pub mod cmake_c;
 pub mod cmake_cpp;
+pub mod cmake_lib;
 pub mod lfc;
 pub mod npm;
 pub mod pnpm;
@@ -25,35 +28,107 @@ pub fn execute_command<'a>(
     config: &'a mut Config,
     which: WhichCapability,
     clone: GitCloneAndCheckoutCap,
+    list_tags: GitListTagsCapability,
+    download: DownloadCapability,
 ) -> BatchBuildResults<'a> {
     let mut result = BatchBuildResults::new();
-    let dependencies = Vec::from_iter(config.dependencies.clone());
+    // the dev-dependencies are always locked, but only the enabled dependencies are loaded
+    let dependencies = config.locked_dependencies().and_then(|locked| {
+        let roots = config
+            .enabled_dependencies()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        Ok((locked, roots))
+    });
+    let (dependencies, roots) = match dependencies {
+        Ok(value) => value,
+        Err(e) => {
+            return result.fail(format!(
+                "cannot enable the features of the package because of {e}"
+            ))
+        }
+    };
 
     match command {
-        CommandSpec::Build(_build) => {
+        CommandSpec::Build(options) => {
             let manager = match DependencyManager::from_dependencies(
-                dependencies.clone(),
-                &PathBuf::from(OUTPUT_DIRECTORY),
+                dependencies,
+                &roots,
+                &config.patches,
+                &config.root_path.join(OUTPUT_DIRECTORY),
+                options.fetch_policy,
                 &clone,
+                &list_tags,
+                &download,
             ) {
                 Ok(value) => value,
                 Err(e) => {
-                    error!("failed to create dependency manager because of {e}");
-                    return result;
+                    return result.fail(format!(
+                        "failed to create dependency manager because of {e}"
+                    ))
                 }
             };
 
-            // enriching the apps with the target properties from the libraries
-            let library_properties = manager.get_target_properties().expect("lib properties");
+            // a library that does not support the target or platform of an app would only
+            // fail deep inside the build system
+            for app in &config.apps {
+                if let Err(e) = manager.lock().check_compatibility(app) {
+                    return result.fail(e);
+                }
+            }
+
+            // enriching the apps with the target properties from the libraries, the native
+            // sources of the libraries are compiled once for all C and C++ apps after the code
+            // of the apps was generated
+            let compiles_native_code = options.compile_target_code
+                && config
+                    .apps
+                    .iter()
+                    .any(|app| app.build_system(&which) == BuildSystem::CMake);
+            let library_properties = if compiles_native_code {
+                cmake_lib::precompiled_properties(
+                    manager.lock(),
+                    &config.root_path.join(OUTPUT_DIRECTORY),
+                    options,
+                )
+            } else {
+                manager.get_target_properties()
+            };
+            let library_properties = match library_properties {
+                Ok(value) => value,
+                Err(e) => {
+                    return result.fail(format!(
+                        "cannot read the target properties of the libraries because of {e}"
+                    ))
+                }
+            };
 
             // merging app with library target properties
             for app in &mut config.apps {
                 if let Err(e) = app.properties.merge(&library_properties) {
-                    error!("cannot merge properties from the libraries with the app. error: {e}");
-                    return result;
+                    return result.fail(format!(
+                        "cannot merge properties from the libraries with the app. error: {e}"
+                    ));
                 }
             }
         }
+        CommandSpec::Update(options) => {
+            // updating only touches the dependencies, the apps are not handed to the backends
+            if let Err(e) = DependencyManager::update(
+                dependencies,
+                &config.patches,
+                &config.root_path.join(OUTPUT_DIRECTORY),
+                &options.packages,
+                options.fetch_policy,
+                &clone,
+                &list_tags,
+                &download,
+            ) {
+                return result.fail(format!("failed to update dependencies because of {e}"));
+            }
+            return result;
+        }
         _ => {}
     }
 
@@ -68,8 +143,13 @@ pub fn execute_command<'a>(
 
     for (build_system, apps) in by_build_system {
         let mut sub_res = BatchBuildResults::for_apps(&apps);
+        // apps of other build systems are not built after a failure either
+        sub_res.aborted = result.aborted;
+        if let CommandSpec::Build(options) = command {
+            sub_res.keep_going(options.keep_going);
+        }
 
-        sub_res.map(|app| {
+        sub_res.step("validate").map(|app| {
             // TODO: Support using lingo as a thin wrapper around west
             if app.platform == Platform::Zephyr {
                 Err(Box::new(LingoError::UseWestBuildToBuildApp))
@@ -124,6 +204,15 @@ pub struct BuildCommandOptions {
     pub max_threads: usize,
     /// if compilation should continue if one of the apps fails building
     pub keep_going: bool,
+    /// Restrictions on resolving and fetching the dependencies.
+    pub fetch_policy: FetchPolicy,
+}
+
+pub struct UpdateCommandOptions {
+    /// Packages that should be updated. If empty all packages are updated.
+    pub packages: Vec<String>,
+    /// Restrictions on resolving and fetching the dependencies.
+    pub fetch_policy: FetchPolicy,
 }
 
 /// Description of a lingo command
@@ -131,7 +220,7 @@ pub enum CommandSpec {
     /// Compile generated code with the target compiler.
     Build(BuildCommandOptions),
     /// Update dependencies
-    Update,
+    Update(UpdateCommandOptions),
     /// Clean build artifacts
     Clean,
 }
@@ -144,8 +233,15 @@ pub trait BatchBackend {
 
 /// Collects build results by app.
 pub struct BatchBuildResults<'a> {
-    results: Vec<(&'a App, BuildResult)>,
+    /// the apps with their result and the step that failed
+    results: Vec<(&'a App, BuildResult, Option<&'static str>)>,
     keep_going: bool,
+    /// the step that is recorded for the apps failing in the next map
+    step: &'static str,
+    /// an app failed without keep going, the remaining apps are not built any more
+    aborted: bool,
+    /// an error that is not caused by a single app, e.g. the dependencies cannot be resolved
+    error: Option<Box<AnyError>>,
 }
 
 impl<'a> BatchBuildResults<'a> {
@@ -154,6 +250,29 @@ impl<'a> BatchBuildResults<'a> {
         Self {
             results: Vec::new(),
             keep_going: false,
+            step: "build",
+            aborted: false,
+            error: None,
+        }
+    }
+
+    /// Records an error that prevented building the apps at all.
+    fn fail(mut self, error: impl Into<Box<AnyError>>) -> Self {
+        self.error = Some(error.into());
+        self
+    }
+
+    /// the error that prevented building the apps at all
+    pub fn error(&self) -> Option<&AnyError> {
+        self.error.as_deref()
+    }
+
+    /// The error that prevented building the apps at all, for commands like update that do
+    /// not build any apps.
+    pub fn into_result(self) -> BuildResult {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
         }
     }
 
@@ -161,34 +280,89 @@ impl<'a> BatchBuildResults<'a> {
     /// then be used by combinators like map and such.
     fn for_apps(apps: &[&'a App]) -> Self {
         Self {
-            results: apps.iter().map(|&a| (a, Ok(()))).collect(),
-            keep_going: false,
+            results: apps.iter().map(|&a| (a, Ok(()), None)).collect(),
+            ..Self::new()
         }
     }
     /// sets the keep going value
-    fn keep_going(&mut self, value: bool) {
+    pub fn keep_going(&mut self, value: bool) {
         self.keep_going = value
     }
 
+    /// Names the step of the following combinators, it is recorded for the apps that fail in it.
+    pub fn step(&mut self, step: &'static str) -> &mut Self {
+        self.step = step;
+        self
+    }
+
+    /// the apps together with the result of their build
+    pub fn results(&self) -> impl Iterator<Item = (&'a App, &BuildResult)> + '_ {
+        self.results.iter().map(|(app, result, _)| (*app, result))
+    }
+
+    /// whether the command failed as a whole or at least one app failed
+    pub fn failed(&self) -> bool {
+        self.error.is_some() || self.results.iter().any(|(_, result, _)| result.is_err())
+    }
+
     /// Print this result collection to standard output.
     pub fn print_results(&self) {
-        for (app, b) in &self.results {
+        if let Some(error) = &self.error {
+            log::error!("{error}");
+        }
+        for (app, b, step) in &self.results {
             match b {
                 Ok(()) => {
                     log::info!("- {}: Success", &app.name);
                 }
                 Err(e) => {
-                    log::error!("- {}: Error: {}", &app.name, e);
+                    log::error!(
+                        "- {}: Error in {}: {}",
+                        &app.name,
+                        step.unwrap_or("build"),
+                        e
+                    );
                 }
             }
         }
     }
 
+    /// The results of the apps in the form that is saved for `--failed`.
+    pub fn report(&self) -> BuildReport {
+        BuildReport {
+            apps: self
+                .results
+                .iter()
+                .map(|(app, result, step)| AppReport {
+                    name: app.name.clone(),
+                    success: result.is_ok(),
+                    step: step.map(String::from),
+                    error: result.as_ref().err().map(|e| e.to_string()),
+                })
+                .collect(),
+        }
+    }
+
     /// Absorb some results into this vector. Apps are not deduplicated, so this
     /// is only ok if the other is disjoint from this result.
     fn append(&mut self, mut other: BatchBuildResults<'a>) {
         self.results.append(&mut other.results);
-        self.results.sort_by_key(|(app, _)| &app.name);
+        self.results.sort_by_key(|(app, _, _)| &app.name);
+        self.aborted |= other.aborted;
+    }
+
+    /// After a failure without keep going the apps that have not failed are marked as not built
+    /// in the current step, which none of them has run.
+    fn abort_remaining(&mut self) {
+        if !self.aborted {
+            return;
+        }
+        for (_app, res, step) in &mut self.results {
+            if let Ok(()) = res {
+                *res = Err(Box::new(LingoError::BuildAborted));
+                *step = Some(self.step);
+            }
+        }
     }
 
     // Note: the duplication of the bodies of the following functions is benign, and
@@ -196,47 +370,54 @@ impl<'a> BatchBuildResults<'a> {
     // a function to get rid of the dup.
 
     /// Map results sequentially. Apps that already have a failing result recorded
-    /// are not fed to the mapping function.
+    /// are not fed to the mapping function. Without keep going the apps after the first
+    /// failure are aborted.
     pub fn map<F>(&mut self, f: F) -> &mut Self
     where
         F: Fn(&'a App) -> BuildResult,
     {
-        self.results.iter_mut().for_each(|(app, res)| {
+        for (app, res, step) in &mut self.results {
             if let Ok(()) = res {
-                *res = f(app);
+                // the apps after a failure do not run the step, the ones before keep their result
+                *res = if self.aborted {
+                    Err(Box::new(LingoError::BuildAborted))
+                } else {
+                    f(app)
+                };
 
-                if (*res).is_err() && !self.keep_going {
-                    panic!(
-                        "build step failed because of {} with main reactor {}!",
-                        &app.name,
-                        &app.main_reactor.display()
-                    );
+                if (*res).is_err() {
+                    *step = Some(self.step);
+                    self.aborted = !self.keep_going;
                 }
             }
-        });
+        }
         self
     }
 
     /// Map results in parallel. Apps that already have a failing result recorded
-    /// are not fed to the mapping function.
+    /// are not fed to the mapping function. Without keep going a failure aborts the
+    /// following steps once all apps went through this one.
     pub fn par_map<F>(&mut self, f: F) -> &mut Self
     where
         F: Fn(&'a App) -> BuildResult + Send + Sync,
     {
-        self.results.par_iter_mut().for_each(|(app, res)| {
+        if self.aborted {
+            self.abort_remaining();
+            return self;
+        }
+
+        // the step is finished for every app, so which apps are aborted does not depend on the
+        // order in which they are built
+        self.results.par_iter_mut().for_each(|(app, res, step)| {
             if let Ok(()) = res {
                 *res = f(app);
 
-                if (*res).is_err() && !self.keep_going {
-                    panic!(
-                        "build step failed with error {:?} because of app {} with main reactor {}!",
-                        &res,
-                        &app.name,
-                        &app.main_reactor.display()
-                    );
+                if (*res).is_err() {
+                    *step = Some(self.step);
                 }
             }
         });
+        self.aborted = !self.keep_going && self.failed();
         self
     }
 
@@ -251,29 +432,220 @@ impl<'a> BatchBuildResults<'a> {
         let vec: Vec<&'a App> = self
             .results
             .iter()
-            .filter_map(|&(app, ref res)| res.as_ref().ok().map(|()| app))
+            .filter_map(|&(app, ref res, _)| res.as_ref().ok().map(|()| app))
             .collect();
 
-        if vec.is_empty() {
+        if vec.is_empty() || self.aborted {
+            self.abort_remaining();
             return self;
         }
 
         match f(&vec) {
             Ok(()) => { /* Do nothing, all apps have succeeded. */ }
             Err(e) => {
-                if !self.keep_going {
-                    panic!("build step failed!");
-                }
-
                 // Mark all as failed for the same reason.
                 let shared: Arc<AnyError> = e.into();
-                for (_app, res) in &mut self.results {
+                for (_app, res, step) in &mut self.results {
                     if let Ok(()) = res {
                         *res = Err(Box::new(LingoError::Shared(shared.clone())));
+                        *step = Some(self.step);
                     }
                 }
+                self.aborted = !self.keep_going;
             }
         }
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{AppFile, ConfigFile, LIBRARY_DIRECTORY};
+    use crate::GitCloneError;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn fails_on_checksum_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(
+            root.join("Lingo.toml"),
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+mylib = { version = "*", tarball = "https://example.com/mylib.tar.gz" }
+"#,
+        )
+        .unwrap();
+        fs::write(
+            root.join("Lingo.lock"),
+            r#"version = 2
+
+[[package]]
+name = "mylib"
+version = "0.1.0"
+source = "tar+https://example.com/mylib.tar.gz"
+checksum = "0000"
+"#,
+        )
+        .unwrap();
+        // the package was fetched before and has been changed since
+        let library = root
+            .join(OUTPUT_DIRECTORY)
+            .join(LIBRARY_DIRECTORY)
+            .join("0000");
+        fs::create_dir_all(&library).unwrap();
+        fs::write(
+            library.join("Lingo.toml"),
+            "[package]\nname = \"mylib\"\nversion = \"0.1.0\"\n\n[lib]\nname = \"mylib\"\nlocation = \".\"\ntarget = \"C\"\nplatform = \"Native\"\n\n[lib.properties]\n",
+        )
+        .unwrap();
+
+        let mut config =
+            toml::from_str::<ConfigFile>(&fs::read_to_string(root.join("Lingo.toml")).unwrap())
+                .unwrap()
+                .to_config(root);
+        let command = CommandSpec::Build(BuildCommandOptions {
+            profile: BuildProfile::Debug,
+            compile_target_code: false,
+            lfc_exec_path: PathBuf::from("lfc"),
+            max_threads: 0,
+            keep_going: false,
+            fetch_policy: FetchPolicy::default(),
+        });
+        let result = execute_command(
+            &command,
+            &mut config,
+            Box::new(|_| Err(crate::WhichError::CannotFindBinaryPath)),
+            Box::new(|_, _, _, _| Err(GitCloneError("offline".into()))),
+            Box::new(|_| Err(GitCloneError("offline".into()))),
+            Box::new(|_, _| Err(crate::DownloadError("offline".into()))),
+        );
+
+        assert!(result.failed());
+        assert!(result
+            .error()
+            .unwrap()
+            .to_string()
+            .contains("Checksum of mylib does not match Lingo.lock"));
+    }
+
+    fn apps(names: &[&str]) -> Vec<App> {
+        names
+            .iter()
+            .map(|name| {
+                AppFile {
+                    name: Some(name.to_string()),
+                    main: Some(format!("src/{name}.lf").into()),
+                    target: TargetLanguage::C,
+                    platform: None,
+                    properties: Default::default(),
+                    expect: None,
+                    tags: vec![],
+                }
+                .convert("package", Path::new("/package"))
+            })
+            .collect()
+    }
+
+    /// the step every app failed in, or `ok`, and whether it was aborted
+    fn outcomes(results: &BatchBuildResults) -> Vec<String> {
+        results
+            .results
+            .iter()
+            .map(|(app, result, step)| match result {
+                Ok(()) => format!("{} ok", app.name),
+                Err(e) => match e.downcast_ref::<LingoError>() {
+                    Some(LingoError::BuildAborted) => {
+                        format!("{} aborted in {}", app.name, step.unwrap())
+                    }
+                    _ => format!("{} failed in {}", app.name, step.unwrap()),
+                },
+            })
+            .collect()
+    }
+
+    fn fail_b(app: &App) -> BuildResult {
+        if app.name == "b" {
+            Err("b is broken".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn map_aborts_the_apps_after_a_failure() {
+        let apps = apps(&["a", "b", "c"]);
+        let apps = apps.iter().collect::<Vec<_>>();
+        let built = std::sync::Mutex::new(vec![]);
+        let mut results = BatchBuildResults::for_apps(&apps);
+        results
+            .step("generate code")
+            .map(|app| {
+                built.lock().unwrap().push(app.name.clone());
+                fail_b(app)
+            })
+            .step("compile")
+            .map(|_| panic!("no app is compiled after a failure"))
+            .step("install")
+            .map(|_| panic!("no app is installed after a failure"));
+
+        assert_eq!(*built.lock().unwrap(), vec!["a", "b"]);
+        assert_eq!(
+            outcomes(&results),
+            vec![
+                "a aborted in compile",
+                "b failed in generate code",
+                "c aborted in generate code",
+            ]
+        );
+    }
+
+    #[test]
+    fn par_map_finishes_the_step_before_aborting() {
+        let apps = apps(&["a", "b", "c"]);
+        let apps = apps.iter().collect::<Vec<_>>();
+        let mut results = BatchBuildResults::for_apps(&apps);
+        results
+            .step("compile")
+            .par_map(fail_b)
+            .step("install")
+            .par_map(|_| panic!("no app is installed after a failure"))
+            .gather(|_| panic!("no app is gathered after a failure"));
+
+        assert_eq!(
+            outcomes(&results),
+            vec![
+                "a aborted in install",
+                "b failed in compile",
+                "c aborted in install",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_going_after_a_failure() {
+        let apps = apps(&["a", "b", "c"]);
+        let apps = apps.iter().collect::<Vec<_>>();
+        let mut results = BatchBuildResults::for_apps(&apps);
+        results.keep_going(true);
+        results
+            .step("generate code")
+            .map(fail_b)
+            .step("compile")
+            .par_map(|app| {
+                assert_ne!(app.name, "b");
+                Ok(())
+            });
+
+        assert_eq!(
+            outcomes(&results),
+            vec!["a ok", "b failed in generate code", "c ok"]
+        );
+    }
+}
@@ -64,6 +64,7 @@ pub fn do_typescript_build(
     };
 
     results
+        .step("install packages")
         .map(|app| {
             let src_postfix = extract_location(&app.main_reactor, &app.root_path)?;
             let path = app.output_root.join("src-gen").join(src_postfix);
@@ -78,6 +79,7 @@ pub fn do_typescript_build(
             execute_command_to_build_result(npm_install)?;
             Ok(())
         })
+        .step("compile")
         .map(|app| {
             let src_postfix = extract_location(&app.main_reactor, &app.root_path)?; // path after src
             let path = app.output_root.join("src-gen").join(src_postfix);
@@ -95,6 +97,7 @@ pub fn do_typescript_build(
 
             Ok(())
         })
+        .step("install")
         .map(|app| {
             fs::create_dir_all(app.output_root.join("bin"))?;
             let file_name = extract_name(&app.main_reactor)?;
@@ -126,7 +129,7 @@ impl BatchBackend for Npm {
                 },
             ),
             CommandSpec::Clean => {
-                results.par_map(|app| {
+                results.step("clean").par_map(|app| {
                     crate::util::default_build_clean(&app.output_root)?;
                     crate::util::delete_subdirs(&app.output_root, &["node_modules", "dist"])?;
                     Ok(())
@@ -17,7 +17,7 @@ impl BatchBackend for Pnpm {
                 },
             ),
             CommandSpec::Clean => {
-                results.par_map(|app| {
+                results.step("clean").par_map(|app| {
                     crate::util::default_build_clean(&app.output_root)?;
                     crate::util::delete_subdirs(&app.output_root, &["node_modules", "dist"])?;
                     Ok(())
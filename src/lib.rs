@@ -18,6 +18,9 @@ pub enum WhichError {
 #[derive(Debug)]
 pub struct GitCloneError(pub String); // TODO: create a more domain-specific error time like the actual git2::Error
 
+#[derive(Debug)]
+pub struct DownloadError(pub String);
+
 impl std::fmt::Display for WhichError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -36,10 +39,36 @@ impl std::fmt::Display for GitCloneError {
     }
 }
 
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl std::error::Error for WhichError {}
 
 impl std::error::Error for GitCloneError {}
 
+impl std::error::Error for DownloadError {}
+
+/// Controls how much of a git repository is cloned
+#[derive(Clone, Copy, Debug)]
+pub struct GitCloneOptions {
+    /// only fetch the history of the checked out revision
+    pub shallow: bool,
+    /// also clone the submodules of the repository
+    pub submodules: bool,
+}
+
+impl Default for GitCloneOptions {
+    fn default() -> Self {
+        GitCloneOptions {
+            shallow: false,
+            submodules: true,
+        }
+    }
+}
+
 pub struct GitUrl<'a>(&'a str);
 
 impl<'a> From<&'a str> for GitUrl<'a> {
@@ -59,5 +88,15 @@ pub type GitCloneCapability<'a> =
     Box<dyn Fn(GitUrl, &std::path::Path) -> Result<(), GitCloneError> + 'a>;
 pub type FsReadCapability<'a> = Box<dyn Fn(&std::path::Path) -> io::Result<String> + 'a>;
 pub type GitCloneAndCheckoutCap<'a> = Box<
-    dyn Fn(GitUrl, &std::path::Path, Option<GitLock>) -> Result<Option<String>, GitCloneError> + 'a,
+    dyn Fn(
+            GitUrl,
+            &std::path::Path,
+            Option<GitLock>,
+            GitCloneOptions,
+        ) -> Result<Option<String>, GitCloneError>
+        + 'a,
 >;
+/// lists the names of all tags of a remote git repository without cloning it
+pub type GitListTagsCapability<'a> = Box<dyn Fn(GitUrl) -> Result<Vec<String>, GitCloneError> + 'a>;
+pub type DownloadCapability<'a> =
+    Box<dyn Fn(&url::Url, &std::path::Path) -> Result<(), DownloadError> + 'a>;
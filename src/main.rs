@@ -2,18 +2,38 @@ use liblingo::args::TargetLanguage;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 use std::{env, io};
 
 use clap::Parser;
 use git2::BranchType::{Local, Remote};
-use git2::{BranchType, Object, ObjectType, Reference, Repository};
-use liblingo::args::InitArgs;
+use git2::{BranchType, FetchOptions, Object, ObjectType, Reference, Repository};
+use liblingo::args::{
+    AddArgs, CacheArgs, CacheCommand, InitArgs, RemoveArgs, SbomArgs, SbomFormat, TestArgs,
+    TreeArgs,
+};
 use liblingo::args::{BuildArgs, Command as ConsoleCommand, CommandLineArgs};
-use liblingo::backends::{BatchBuildResults, BuildCommandOptions, CommandSpec};
-use liblingo::package::tree::GitLock;
-use liblingo::package::{Config, ConfigFile};
+use liblingo::backends::{
+    BatchBuildResults, BuildCommandOptions, CommandSpec, UpdateCommandOptions,
+};
+use liblingo::package::cache::Cache;
+use liblingo::package::editor::ConfigEditor;
+use liblingo::package::graph::DependencyGraph;
+use liblingo::package::lock::DependencyLock;
+use liblingo::package::management::{DependencyManager, FetchPolicy};
+use liblingo::package::sbom::BillOfMaterials;
+use liblingo::package::tree::{GitLock, PackageDetails};
+use liblingo::package::{Config, ConfigFile, OUTPUT_DIRECTORY};
+use liblingo::util::build_report::{build_report_file, BuildReport};
 use liblingo::util::errors::{BuildResult, LingoError};
-use liblingo::{GitCloneAndCheckoutCap, GitCloneError, GitUrl, WhichCapability, WhichError};
+use liblingo::util::golden::check_output;
+use liblingo::util::selection;
+use liblingo::util::testing::{run_tests, TestStatus};
+use liblingo::util::time;
+use liblingo::{
+    DownloadCapability, DownloadError, GitCloneAndCheckoutCap, GitCloneError, GitCloneOptions,
+    GitListTagsCapability, GitUrl, WhichCapability, WhichError,
+};
 
 fn do_which(cmd: &str) -> Result<PathBuf, WhichError> {
     which::which(cmd).map_err(|err| match err {
@@ -42,49 +62,137 @@ fn get_branch<'a>(
     ))
 }
 
+/// Fetches only the requested revision with a depth of one into a new repository
+fn shallow_fetch(
+    url: &str,
+    outpath: &Path,
+    git_tag: Option<&GitLock>,
+) -> Result<Repository, git2::Error> {
+    let repo = Repository::init(outpath)?;
+    let refspec = match git_tag {
+        Some(GitLock::Tag(tag)) => format!("+refs/tags/{tag}:refs/tags/{tag}"),
+        Some(GitLock::Branch(branch)) => {
+            let branch = branch.strip_prefix("origin/").unwrap_or(branch);
+            format!("+refs/heads/{branch}:refs/remotes/origin/{branch}")
+        }
+        Some(GitLock::Rev(rev)) => rev.clone(),
+        None => "+HEAD:refs/remotes/origin/HEAD".to_string(),
+    };
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.depth(1);
+    repo.remote("origin", url)?
+        .fetch(&[refspec], Some(&mut fetch_options), None)?;
+    Ok(repo)
+}
+
+fn update_submodules(repo: &Repository) -> Result<(), git2::Error> {
+    for mut submodule in repo.submodules()? {
+        submodule.update(true, None)?;
+        update_submodules(&submodule.open()?)?;
+    }
+    Ok(())
+}
+
 fn do_clone_and_checkout(
     git_url: GitUrl,
     outpath: &Path,
     git_tag: Option<GitLock>,
+    options: GitCloneOptions,
 ) -> Result<Option<String>, GitCloneError> {
-    let repo = Repository::clone_recurse(<&str>::from(git_url), outpath)
-        .map_err(|_| GitCloneError("clone failed".to_string()))?;
-    let mut git_rev = None;
-
-    if let Some(git_lock) = git_tag {
-        let (object, reference) = match git_lock {
-            GitLock::Tag(tag) => repo
-                .revparse_ext(&tag)
-                .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
-            GitLock::Branch(branch) => {
-                if let Ok(val) = get_branch(&repo, &branch, Local) {
-                    val
-                } else {
-                    get_branch(&repo, &branch, Remote)?
-                }
-            }
-            GitLock::Rev(rev) => repo
-                .revparse_ext(&rev)
-                .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
-        };
-        repo.checkout_tree(&object, None)
-            .map_err(|e| GitCloneError(format!("cannot checkout rev {e}")))?;
-
-        // TODO: this produces hard to debug output
-
-        match reference {
-            // gref is an actual reference like branches or tags
-            Some(gref) => {
-                git_rev = gref.target().map(|v| v.to_string());
-                repo.set_head(gref.name().unwrap())
+    let url = <&str>::from(git_url);
+
+    // libgit2 cannot fetch shallow from every remote, e.g. not from local repositories,
+    // in this case the whole repository is cloned
+    let shallow_repo = if options.shallow {
+        match shallow_fetch(url, outpath, git_tag.as_ref()) {
+            Ok(repo) => Some(repo),
+            Err(e) => {
+                log::warn!("cannot fetch {url} shallow, cloning the whole repository: {e}");
+                std::fs::remove_dir_all(outpath).map_err(|e| {
+                    GitCloneError(format!("cannot remove {}: {e}", outpath.display()))
+                })?;
+                None
             }
-            // this is a commit, not a reference
-            None => repo.set_head_detached(object.id()),
         }
-        .map_err(|_| GitCloneError("cannot checkout rev".to_string()))?;
+    } else {
+        None
+    };
+    let repo = match shallow_repo {
+        Some(repo) => repo,
+        None => Repository::clone(url, outpath)
+            .map_err(|e| GitCloneError(format!("clone failed {e}")))?,
+    };
+
+    let (object, reference) = match git_tag {
+        Some(GitLock::Tag(tag)) => repo
+            .revparse_ext(&tag)
+            .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
+        Some(GitLock::Branch(branch)) => get_branch(&repo, &branch, Local)
+            .or_else(|_| get_branch(&repo, &branch, Remote))
+            .or_else(|_| get_branch(&repo, &format!("origin/{branch}"), Remote))?,
+        Some(GitLock::Rev(rev)) => repo
+            .revparse_ext(&rev)
+            .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
+        None => repo
+            .revparse_ext("HEAD")
+            .or_else(|_| repo.revparse_ext("origin/HEAD"))
+            .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
+    };
+    repo.checkout_tree(&object, None)
+        .map_err(|e| GitCloneError(format!("cannot checkout rev {e}")))?;
+
+    // TODO: this produces hard to debug output
+
+    match reference {
+        // gref is an actual reference like branches or tags
+        Some(gref) => repo.set_head(gref.name().unwrap()),
+        // this is a commit, not a reference
+        None => repo.set_head_detached(object.id()),
+    }
+    .map_err(|_| GitCloneError("cannot checkout rev".to_string()))?;
+
+    if options.submodules {
+        update_submodules(&repo)
+            .map_err(|e| GitCloneError(format!("cannot update submodules {e}")))?;
     }
 
-    Ok(git_rev)
+    let commit = object
+        .peel_to_commit()
+        .map_err(|e| GitCloneError(format!("cannot find commit {e}")))?;
+    Ok(Some(commit.id().to_string()))
+}
+
+fn do_list_tags(git_url: GitUrl) -> Result<Vec<String>, GitCloneError> {
+    let url = <&str>::from(git_url);
+    let mut remote = git2::Remote::create_detached(url)
+        .map_err(|e| GitCloneError(format!("invalid remote {e}")))?;
+    remote
+        .connect(git2::Direction::Fetch)
+        .map_err(|e| GitCloneError(format!("cannot connect to remote {e}")))?;
+
+    let mut tags = remote
+        .list()
+        .map_err(|e| GitCloneError(format!("cannot list remote {e}")))?
+        .iter()
+        .filter_map(|head| head.name().strip_prefix("refs/tags/"))
+        // annotated tags are listed a second time with the commit they point to
+        .map(|tag| tag.trim_end_matches("^{}").to_string())
+        .collect::<Vec<_>>();
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
+
+fn do_download(url: &url::Url, outpath: &Path) -> Result<(), DownloadError> {
+    let response = ureq::get(url.as_str())
+        .call()
+        .map_err(|e| DownloadError(format!("cannot download {url}: {e}")))?;
+    let mut file = std::fs::File::create(outpath)
+        .map_err(|e| DownloadError(format!("cannot create {}: {e}", outpath.display())))?;
+    io::copy(&mut response.into_reader(), &mut file)
+        .map_err(|e| DownloadError(format!("cannot download {url}: {e}")))?;
+    Ok(())
 }
 
 fn do_read_to_string(p: &Path) -> io::Result<String> {
@@ -108,9 +216,11 @@ fn main() {
             .map(|cf| cf.to_config(path.parent().unwrap()))
     });
 
+    // e.g. unknown app names or tests that cannot be discovered, nothing is built then
     let result: BuildResult = validate(&mut wrapped_config, &args.command);
     if result.is_err() {
-        print_res(result)
+        print_res(result);
+        std::process::exit(1);
     }
 
     let result = execute_command(
@@ -121,8 +231,19 @@ fn main() {
     );
 
     match result {
-        CommandResult::Batch(res) => res.print_results(),
-        CommandResult::Single(res) => print_res(res),
+        CommandResult::Batch(res) => {
+            res.print_results();
+            if res.failed() {
+                std::process::exit(1);
+            }
+        }
+        CommandResult::Single(res) => {
+            // e.g. failed tests have to fail the CI job
+            if res.is_err() {
+                print_res(res);
+                std::process::exit(1);
+            }
+        }
     }
 }
 
@@ -137,27 +258,49 @@ fn print_res(result: BuildResult) {
 
 fn validate(config: &mut Option<Config>, command: &ConsoleCommand) -> BuildResult {
     match (config, command) {
-        (Some(config), ConsoleCommand::Build(build))
-        | (Some(config), ConsoleCommand::Run(build)) => {
-            let unknown_names = build
-                .apps
-                .iter()
-                .filter(|&name| !config.apps.iter().any(|app| &app.name == name))
-                .cloned()
-                .collect::<Vec<_>>();
-            if !unknown_names.is_empty() {
-                return Err(Box::new(LingoError::UnknownAppNames(unknown_names)));
-            }
-            // Now remove the apps that were not selected by the CLI
-            if !build.apps.is_empty() {
-                config.apps.retain(|app| build.apps.contains(&app.name));
-            }
-            Ok(())
+        (Some(config), ConsoleCommand::Test(test)) => {
+            config.discover_tests()?;
+            config.test_build(test.include_apps);
+            select_apps(config, &test.build, "test")
         }
+        (Some(config), ConsoleCommand::Build(build)) => select_apps(config, build, "build"),
+        (Some(config), ConsoleCommand::Run(run)) => select_apps(config, &run.build, "run"),
         _ => Ok(()),
     }
 }
 
+/// removes the apps that were not selected on the command line
+fn select_apps(config: &mut Config, build: &BuildArgs, command: &str) -> BuildResult {
+    let last_build = if build.failed {
+        Some(BuildReport::read(&build_report_path(config, command))?)
+    } else {
+        None
+    };
+    // the apps are only replaced once the selection succeeded
+    config.apps = selection::select_apps(config.apps.clone(), build, last_build.as_ref())?;
+    if build.failed && config.apps.is_empty() {
+        log::info!("No app failed in the last build");
+    }
+    Ok(())
+}
+
+fn build_report_path(config: &Config, command: &str) -> PathBuf {
+    config
+        .root_path
+        .join(OUTPUT_DIRECTORY)
+        .join(build_report_file(command))
+}
+
+/// saves the results for `--failed`, a build that did not get to the apps keeps the old results
+fn save_build_report(report: &BuildReport, path: &Path) {
+    if report.apps.is_empty() {
+        return;
+    }
+    if let Err(e) = report.write(path) {
+        log::error!("cannot write the build results to {}: {e}", path.display());
+    }
+}
+
 fn execute_command<'a>(
     config: &'a mut Option<Config>,
     command: ConsoleCommand,
@@ -168,26 +311,71 @@ fn execute_command<'a>(
         (_, ConsoleCommand::Init(init_config)) => {
             CommandResult::Single(do_init(init_config, &git_clone_capability))
         }
+        (_, ConsoleCommand::Cache(cache_args)) => CommandResult::Single(do_cache(&cache_args)),
         (None, _) => CommandResult::Single(Err(Box::new(io::Error::new(
             ErrorKind::NotFound,
             "Error: Missing Lingo.toml file",
         )))),
         (Some(config), ConsoleCommand::Build(build_command_args)) => {
-            CommandResult::Batch(build(&build_command_args, config))
+            let report_path = build_report_path(config, "build");
+            let res = build(&build_command_args, config);
+            save_build_report(&res.report(), &report_path);
+            CommandResult::Batch(res)
         }
-        (Some(config), ConsoleCommand::Run(build_command_args)) => {
-            let mut res = build(&build_command_args, config);
-            res.map(|app| {
+        (Some(config), ConsoleCommand::Run(run_args)) => {
+            let report_path = build_report_path(config, "run");
+            let mut res = build(&run_args.build, config);
+            res.keep_going(run_args.build.keep_going);
+            res.step("run").map(|app| {
                 let mut command = Command::new(app.executable_path());
-                liblingo::util::run_and_capture(&mut command)?;
+                let (_, stdout, stderr) = liblingo::util::run_and_capture(&mut command)?;
+                if let Some(expected) = &app.expect {
+                    if let Some(mismatches) =
+                        check_output(expected, &stdout, &stderr, run_args.bless)?
+                    {
+                        return Err(Box::new(LingoError::UnexpectedOutput(
+                            app.name.clone(),
+                            mismatches,
+                        )));
+                    }
+                }
                 Ok(())
             });
+            save_build_report(&res.report(), &report_path);
             CommandResult::Batch(res)
         }
+        (Some(config), ConsoleCommand::Test(mut test_args)) => {
+            // a test that cannot be built is reported as failed like every other test
+            test_args.build.keep_going = true;
+            let suite = config.package.name.clone();
+            let report_path = build_report_path(config, "test");
+            let res = build(&test_args.build, config);
+            CommandResult::Single(do_test(&test_args, &suite, &res, &report_path))
+        }
+        (Some(config), ConsoleCommand::Update(update_args)) => CommandResult::Batch(run_command(
+            CommandSpec::Update(UpdateCommandOptions {
+                packages: update_args.packages,
+                fetch_policy: update_args.lock.fetch_policy(),
+            }),
+            config,
+            true,
+        )),
+        (Some(config), ConsoleCommand::Add(add_args)) => {
+            CommandResult::Single(do_add(&add_args, config))
+        }
+        (Some(config), ConsoleCommand::Remove(remove_args)) => {
+            CommandResult::Single(do_remove(&remove_args, config))
+        }
+        (Some(config), ConsoleCommand::Tree(tree_args)) => {
+            CommandResult::Single(do_tree(&tree_args, config))
+        }
+        (Some(config), ConsoleCommand::Licenses) => CommandResult::Single(do_licenses(config)),
+        (Some(config), ConsoleCommand::Sbom(sbom_args)) => {
+            CommandResult::Single(do_sbom(&sbom_args, config))
+        }
         (Some(config), ConsoleCommand::Clean) => {
             CommandResult::Batch(run_command(CommandSpec::Clean, config, true))
         }
-        _ => todo!(),
     }
 }
 
@@ -201,6 +389,193 @@ fn do_init(init_config: InitArgs, git_clone_capability: &GitCloneAndCheckoutCap)
     )
 }
 
+/// Adds the dependency to Lingo.toml once it was resolved, so a dependency that cannot be
+/// resolved leaves Lingo.toml untouched. Without `--version` the resolved version is pinned.
+fn do_add(add_args: &AddArgs, config: &mut Config) -> BuildResult {
+    let mut package = PackageDetails::try_from(add_args)?;
+    let mut editor = ConfigEditor::open(&config.root_path.join("Lingo.toml"))?;
+    config
+        .dependencies
+        .insert(add_args.name.clone(), package.clone());
+    update_dependency(add_args.name.clone(), config).into_result()?;
+
+    if add_args.version.is_none() {
+        let lock = DependencyLock::read(&config.root_path.join("Lingo.lock"))?;
+        if let Some(locked) = lock.dependencies.get(&add_args.name) {
+            package.require_compatible(&locked.version)?;
+        }
+    }
+    editor.add_dependency(&add_args.name, &package)?;
+    editor.write()?;
+    config.dependencies.insert(add_args.name.clone(), package);
+    Ok(())
+}
+
+/// Removes the dependency from Lingo.toml once the remaining dependencies were resolved.
+fn do_remove(remove_args: &RemoveArgs, config: &mut Config) -> BuildResult {
+    let mut editor = ConfigEditor::open(&config.root_path.join("Lingo.toml"))?;
+    if !editor.remove_dependency(&remove_args.name)? {
+        return Err(Box::new(LingoError::UnknownDependencyNames(vec![
+            remove_args.name.clone(),
+        ])));
+    }
+    config.dependencies.remove(&remove_args.name);
+    update_dependency(remove_args.name.clone(), config).into_result()?;
+    editor.write()?;
+    Ok(())
+}
+
+/// resolves the dependencies of the package, reusing the Lingo.lock if it is up to date
+fn resolve_dependencies(config: &Config, policy: FetchPolicy) -> anyhow::Result<DependencyManager> {
+    let roots = config
+        .enabled_dependencies()?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    DependencyManager::from_dependencies(
+        config.locked_dependencies()?,
+        &roots,
+        &config.patches,
+        &config.root_path.join(OUTPUT_DIRECTORY),
+        policy,
+        &(Box::new(do_clone_and_checkout) as GitCloneAndCheckoutCap),
+        &(Box::new(do_list_tags) as GitListTagsCapability),
+        &(Box::new(do_download) as DownloadCapability),
+    )
+}
+
+fn do_tree(tree_args: &TreeArgs, config: &Config) -> BuildResult {
+    let manager = resolve_dependencies(config, FetchPolicy::default())?;
+    let graph = DependencyGraph::new(config, manager.lock())?;
+
+    if tree_args.duplicates {
+        print!("{}", graph.render_duplicates());
+    } else {
+        print!(
+            "{}",
+            graph.render_tree(tree_args.invert.as_deref(), tree_args.depth)
+        );
+    }
+    Ok(())
+}
+
+fn bill_of_materials(config: &Config) -> anyhow::Result<BillOfMaterials> {
+    // the bill of materials describes the existing Lingo.lock, it is neither created nor updated
+    let manager = resolve_dependencies(
+        config,
+        FetchPolicy {
+            locked: true,
+            ..Default::default()
+        },
+    )?;
+    BillOfMaterials::new(config, manager.lock())
+}
+
+fn do_licenses(config: &Config) -> BuildResult {
+    print!("{}", bill_of_materials(config)?.render_licenses());
+    Ok(())
+}
+
+fn do_sbom(sbom_args: &SbomArgs, config: &Config) -> BuildResult {
+    let bom = bill_of_materials(config)?;
+    let document = match sbom_args.format {
+        SbomFormat::CycloneDx => bom.cyclonedx(),
+        SbomFormat::Spdx => bom.spdx(),
+    };
+    let text = serde_json::to_string_pretty(&document)?;
+
+    match &sbom_args.output {
+        Some(path) => std::fs::write(path, text + "\n")?,
+        None => println!("{text}"),
+    }
+    Ok(())
+}
+
+fn do_test(
+    test_args: &TestArgs,
+    suite: &str,
+    builds: &BatchBuildResults,
+    report_path: &Path,
+) -> BuildResult {
+    // without the dependencies no test was built, which is not an empty test run
+    if let Some(error) = builds.error() {
+        return Err(error.to_string().into());
+    }
+
+    let report = run_tests(
+        suite,
+        builds.results(),
+        Duration::from_secs(test_args.timeout),
+        !test_args.build.no_compile,
+        test_args.bless,
+    );
+    print!("{}", report.render_summary());
+
+    // tests that were built but failed have to be selected by --failed as well
+    let mut build_report = builds.report();
+    for outcome in report.outcomes.iter().filter(|outcome| outcome.failed()) {
+        if !matches!(outcome.status, TestStatus::BuildFailed(_)) {
+            build_report.fail(&outcome.name, "test", outcome.status.to_string());
+        }
+    }
+    save_build_report(&build_report, report_path);
+
+    if let Some(path) = &test_args.junit {
+        std::fs::write(path, report.junit())?;
+    }
+    if report.failed() > 0 {
+        return Err(Box::new(LingoError::TestsFailed(
+            report.failed(),
+            report.total(),
+        )));
+    }
+    Ok(())
+}
+
+fn do_cache(cache_args: &CacheArgs) -> BuildResult {
+    let cache = Cache::open().ok_or(LingoError::NoCacheLocation)?;
+    match &cache_args.command {
+        CacheCommand::List => {
+            let now = time::now();
+            for entry in cache.entries()? {
+                println!(
+                    "{} ({} KiB, last used {} days ago)",
+                    entry.key,
+                    entry.size() / 1024,
+                    now.saturating_sub(entry.last_used) / (24 * 60 * 60)
+                );
+            }
+        }
+        CacheCommand::Clean => {
+            let removed = cache.clean()?;
+            println!(
+                "Removed {} packages from {}",
+                removed.len(),
+                cache.root().display()
+            );
+        }
+        CacheCommand::Gc(gc_args) => {
+            let removed = cache.gc(Duration::from_secs(gc_args.max_age * 24 * 60 * 60))?;
+            for entry in &removed {
+                println!("Removed {}", entry.key);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// refreshes the lock file after `name` was added or removed, all other packages stay pinned
+fn update_dependency(name: String, config: &mut Config) -> BatchBuildResults<'_> {
+    run_command(
+        CommandSpec::Update(UpdateCommandOptions {
+            packages: vec![name],
+            fetch_policy: FetchPolicy::default(),
+        }),
+        config,
+        true,
+    )
+}
+
 fn build<'a>(args: &BuildArgs, config: &'a mut Config) -> BatchBuildResults<'a> {
     run_command(
         CommandSpec::Build(BuildCommandOptions {
@@ -210,19 +585,26 @@ fn build<'a>(args: &BuildArgs, config: &'a mut Config) -> BatchBuildResults<'a>
                 .expect("TODO replace me"),
             max_threads: args.threads,
             keep_going: args.keep_going,
+            fetch_policy: args.lock.fetch_policy(),
         }),
         config,
         args.keep_going,
     )
 }
 
-fn run_command(task: CommandSpec, config: &mut Config, _fail_at_end: bool) -> BatchBuildResults {
+fn run_command(
+    task: CommandSpec,
+    config: &mut Config,
+    _fail_at_end: bool,
+) -> BatchBuildResults<'_> {
     let _apps = config.apps.iter().collect::<Vec<_>>();
     liblingo::backends::execute_command(
         &task,
         config,
         Box::new(do_which),
         Box::new(do_clone_and_checkout),
+        Box::new(do_list_tags),
+        Box::new(do_download),
     )
 }
 
@@ -230,3 +612,152 @@ enum CommandResult<'a> {
     Batch(BatchBuildResults<'a>),
     Single(BuildResult),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use liblingo::package::cache::CACHE_ENV_VARIABLE;
+    use std::fs;
+    use std::sync::Once;
+
+    /// keeps the packages fetched by the tests out of the cache of the user
+    fn isolate_cache() {
+        static ISOLATE: Once = Once::new();
+        ISOLATE.call_once(|| {
+            let cache = env::temp_dir().join(format!("lingo-test-cache-{}", std::process::id()));
+            env::set_var(CACHE_ENV_VARIABLE, cache);
+        });
+    }
+
+    /// creates a repository with one commit containing the files, tagged as `v1.0.0`
+    fn repository(path: &Path, files: &[(&str, &str)]) -> (Repository, git2::Oid) {
+        let repo = Repository::init(path).unwrap();
+        for (name, content) in files {
+            let file = path.join(name);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        }
+        let commit = commit(&repo);
+        repo.tag_lightweight("v1.0.0", &repo.find_object(commit, None).unwrap(), false)
+            .unwrap();
+        (repo, commit)
+    }
+
+    fn commit(repo: &Repository) -> git2::Oid {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("lingo", "lingo@example.com").unwrap();
+        let parents = repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .into_iter()
+            .collect::<Vec<_>>();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "commit",
+            &tree,
+            &parents.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    fn file_url(path: &Path) -> String {
+        url::Url::from_file_path(path).unwrap().to_string()
+    }
+
+    #[test]
+    fn fetches_a_subdirectory() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let remote = dir.path().join("remote");
+        repository(
+            &remote,
+            &[
+                ("README.md", "repository\n"),
+                ("packages/mylib/Lingo.toml", "[package]\n"),
+            ],
+        );
+
+        let mut details = toml::from_str::<PackageDetails>(&format!(
+            "version = \"*\"\ngit = \"{}\"\ntag = \"v1.0.0\"\nsubdir = \"packages/mylib\"\n",
+            file_url(&remote)
+        ))
+        .unwrap();
+        let destination = dir.path().join("mylib");
+        details
+            .fetch(
+                &destination,
+                false,
+                &(Box::new(do_clone_and_checkout) as GitCloneAndCheckoutCap),
+                &(Box::new(do_download) as DownloadCapability),
+            )
+            .unwrap();
+
+        assert!(destination.join("Lingo.toml").is_file());
+        assert!(!destination.join("README.md").exists());
+    }
+
+    #[test]
+    fn falls_back_to_a_full_clone_when_shallow_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote = dir.path().join("remote");
+        let (_, commit) = repository(&remote, &[("Lingo.toml", "[package]\n")]);
+
+        let destination = dir.path().join("checkout");
+        let rev = do_clone_and_checkout(
+            GitUrl::from(file_url(&remote).as_str()),
+            &destination,
+            Some(GitLock::Tag("v1.0.0".to_string())),
+            GitCloneOptions {
+                shallow: true,
+                submodules: false,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(rev, Some(commit.to_string()));
+        assert!(destination.join("Lingo.toml").is_file());
+    }
+
+    #[test]
+    fn clones_submodules_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("nested");
+        repository(&nested, &[("nested.txt", "nested\n")]);
+
+        let remote = dir.path().join("remote");
+        let (repo, _) = repository(&remote, &[("Lingo.toml", "[package]\n")]);
+        let mut submodule = repo
+            .submodule(&file_url(&nested), Path::new("vendor/nested"), true)
+            .unwrap();
+        submodule.clone(None).unwrap();
+        submodule.add_finalize().unwrap();
+        commit(&repo);
+
+        for submodules in [true, false] {
+            let destination = dir.path().join(format!("checkout-{submodules}"));
+            do_clone_and_checkout(
+                GitUrl::from(file_url(&remote).as_str()),
+                &destination,
+                None,
+                GitCloneOptions {
+                    shallow: false,
+                    submodules,
+                },
+            )
+            .unwrap();
+
+            assert_eq!(
+                destination.join("vendor/nested/nested.txt").is_file(),
+                submodules
+            );
+        }
+    }
+}
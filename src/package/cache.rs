@@ -0,0 +1,204 @@
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::util::{checksum, copy_recursively, time};
+
+/// environment variable that overrides the location of the download cache
+pub const CACHE_ENV_VARIABLE: &str = "LINGO_CACHE";
+
+/// file inside every cache entry that describes the entry
+const ENTRY_FILE: &str = "entry.toml";
+
+/// directory inside every cache entry that contains the package
+const SOURCE_DIRECTORY: &str = "source";
+
+/// A package that is stored in the cache
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CacheEntry {
+    /// source url together with the resolved revision or the archive hash
+    pub key: String,
+    /// seconds since the unix epoch when this entry was last stored or restored
+    pub last_used: u64,
+    /// directory of the entry
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl CacheEntry {
+    /// size of the cached package in bytes
+    pub fn size(&self) -> u64 {
+        directory_size(&self.path.join(SOURCE_DIRECTORY))
+    }
+}
+
+/// Per-user cache of fetched packages that is shared between all projects. Packages are
+/// stored by their source url plus the resolved git revision or the sha256 of the archive,
+/// hence an entry never changes after it was written.
+pub struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    /// opens the cache at its default location, if one can be determined
+    pub fn open() -> Option<Cache> {
+        Self::location().map(|root| Cache { root })
+    }
+
+    /// `$LINGO_CACHE` if set, otherwise `$XDG_CACHE_HOME/lingo` or `~/.cache/lingo`
+    pub fn location() -> Option<PathBuf> {
+        if let Some(path) = env::var_os(CACHE_ENV_VARIABLE) {
+            return Some(PathBuf::from(path));
+        }
+        if let Some(path) = env::var_os("XDG_CACHE_HOME") {
+            return Some(PathBuf::from(path).join("lingo"));
+        }
+        env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(".cache").join("lingo"))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// directory of the entry with the given key
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.root
+            .join(checksum::hex(&Sha256::digest(key.as_bytes())))
+    }
+
+    /// Copies the cached package into the destination. Returns false if the cache does not
+    /// contain the package.
+    pub fn restore(&self, key: &str, destination: &Path) -> io::Result<bool> {
+        let entry = self.entry_path(key);
+        if !entry.join(ENTRY_FILE).is_file() {
+            return Ok(false);
+        }
+
+        copy_recursively(entry.join(SOURCE_DIRECTORY), destination)?;
+        write_entry(&entry, key)?;
+        Ok(true)
+    }
+
+    /// Stores a copy of the package. Existing entries are kept as they are.
+    pub fn store(&self, key: &str, source: &Path) -> io::Result<()> {
+        let entry = self.entry_path(key);
+        if entry.join(ENTRY_FILE).is_file() {
+            return Ok(());
+        }
+
+        // the entry is assembled next to its final location and moved in one step, so other
+        // processes never see a half written entry
+        fs::create_dir_all(&self.root)?;
+        let staging = tempfile::tempdir_in(&self.root)?;
+        copy_recursively(source, staging.path().join(SOURCE_DIRECTORY))?;
+        write_entry(staging.path(), key)?;
+
+        let _ = fs::remove_dir_all(&entry);
+        match fs::rename(staging.path(), &entry) {
+            // another process stored the same entry in the meantime
+            Err(_) if entry.join(ENTRY_FILE).is_file() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// all entries of the cache sorted by their key
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        if !self.root.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut entries = Vec::new();
+        for dir in fs::read_dir(&self.root)? {
+            let path = dir?.path();
+            // directories without an entry file are incomplete and skipped
+            if let Ok(text) = fs::read_to_string(path.join(ENTRY_FILE)) {
+                if let Ok(mut entry) = toml::from_str::<CacheEntry>(&text) {
+                    entry.path = path;
+                    entries.push(entry);
+                }
+            }
+        }
+
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+
+    /// Removes all entries and returns them. Only directories with an entry file are removed,
+    /// as `$LINGO_CACHE` may point to a directory that contains other files as well.
+    pub fn clean(&self) -> io::Result<Vec<CacheEntry>> {
+        let entries = self.entries()?;
+        for entry in &entries {
+            fs::remove_dir_all(&entry.path)?;
+        }
+        Ok(entries)
+    }
+
+    /// Removes all entries that have not been used within `max_age` and returns them.
+    pub fn gc(&self, max_age: Duration) -> io::Result<Vec<CacheEntry>> {
+        let now = time::now();
+        let mut removed = Vec::new();
+        for entry in self.entries()? {
+            if now.saturating_sub(entry.last_used) > max_age.as_secs() {
+                fs::remove_dir_all(&entry.path)?;
+                removed.push(entry);
+            }
+        }
+        Ok(removed)
+    }
+}
+
+fn write_entry(path: &Path, key: &str) -> io::Result<()> {
+    let entry = CacheEntry {
+        key: key.to_string(),
+        last_used: time::now(),
+        path: PathBuf::new(),
+    };
+    let text = toml::to_string(&entry).map_err(io::Error::other)?;
+    fs::write(path.join(ENTRY_FILE), text)
+}
+
+fn directory_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => directory_size(&entry.path()),
+                    _ => entry.metadata().map(|meta| meta.len()).unwrap_or_default(),
+                })
+                .sum()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_only_removes_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache {
+            root: dir.path().to_path_buf(),
+        };
+        let package = tempfile::tempdir().unwrap();
+        fs::write(package.path().join("Lingo.toml"), "[package]\n").unwrap();
+        cache
+            .store("tar+https://example.com/lib.tar.gz#abc", package.path())
+            .unwrap();
+        fs::create_dir_all(dir.path().join("documents")).unwrap();
+        fs::write(dir.path().join("documents/notes.txt"), "keep me").unwrap();
+
+        let removed = cache.clean().unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(cache.entries().unwrap().is_empty());
+        assert!(dir.path().join("documents/notes.txt").is_file());
+    }
+}
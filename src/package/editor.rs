@@ -0,0 +1,159 @@
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
+
+use crate::package::tree::{GitLock, PackageDetails, ProjectSource};
+
+/// Edits a Lingo.toml in place. In contrast to `ConfigFile::write` this keeps comments,
+/// ordering and formatting of the file intact.
+pub struct ConfigEditor {
+    /// path to the Lingo.toml
+    path: PathBuf,
+    /// parsed document that is modified
+    document: DocumentMut,
+}
+
+impl ConfigEditor {
+    pub fn open(path: &Path) -> io::Result<ConfigEditor> {
+        let document = fs::read_to_string(path)?
+            .parse::<DocumentMut>()
+            .map_err(|e| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("failed to convert string to toml: {}", e),
+                )
+            })?;
+
+        Ok(ConfigEditor {
+            path: path.to_path_buf(),
+            document,
+        })
+    }
+
+    /// returns the table with the given name and creates it if it doesn't exist yet
+    fn table_mut(&mut self, name: &str) -> io::Result<&mut Table> {
+        self.document
+            .entry(name)
+            .or_insert(Item::Table(Table::new()))
+            .as_table_mut()
+            .ok_or(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("[{}] in {} is not a table", name, self.path.display()),
+            ))
+    }
+
+    /// adds the dependency or replaces it if a dependency with the same name already exists
+    pub fn add_dependency(&mut self, name: &str, package: &PackageDetails) -> io::Result<()> {
+        let dependencies = self.table_mut("dependencies")?;
+        dependencies.insert(
+            name,
+            Item::Value(Value::InlineTable(package.to_inline_table())),
+        );
+        Ok(())
+    }
+
+    /// removes the dependency and returns if the dependency was present
+    pub fn remove_dependency(&mut self, name: &str) -> io::Result<bool> {
+        let dependencies = self.table_mut("dependencies")?;
+        Ok(dependencies.remove(name).is_some())
+    }
+
+    pub fn write(&self) -> io::Result<()> {
+        fs::write(&self.path, self.document.to_string())
+    }
+}
+
+impl PackageDetails {
+    /// the inline table as it is written into the `[dependencies]` section of the Lingo.toml
+    fn to_inline_table(&self) -> InlineTable {
+        let mut table = InlineTable::new();
+        table.insert("version", self.version.to_string().into());
+
+        match &self.mutual_exclusive {
+            ProjectSource::Git(url) => table.insert("git", url.as_str().into()),
+            ProjectSource::TarBall(url) => table.insert("tarball", url.as_str().into()),
+            ProjectSource::Path(path) => table.insert("path", path.display().to_string().into()),
+            ProjectSource::Registry(Some(registry)) => table.insert("registry", registry.into()),
+            ProjectSource::Registry(None) => None,
+        };
+
+        match &self.git_tag {
+            Some(GitLock::Tag(tag)) => table.insert("tag", tag.into()),
+            Some(GitLock::Branch(branch)) => table.insert("branch", branch.into()),
+            Some(GitLock::Rev(rev)) => table.insert("rev", rev.into()),
+            None => None,
+        };
+
+        if let Some(sha256) = &self.sha256 {
+            table.insert("sha256", sha256.into());
+        }
+
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use versions::Requirement;
+
+    #[test]
+    fn keeps_comments_and_ordering() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Lingo.toml");
+        fs::write(
+            &path,
+            r#"# the example package
+[package]
+name = "example"
+version = "0.1.0"
+
+[dependencies]
+# used by the server
+mqtt = { version = ">=0.1", git = "https://github.com/LF-Community/mqtt.git" }
+old = { version = "*", path = "../old" } # going away
+zlib = { version = "^1.3" }
+
+[[app]]
+name = "server" # the main app
+target = "C"
+"#,
+        )
+        .unwrap();
+
+        let mut editor = ConfigEditor::open(&path).unwrap();
+        assert!(editor.remove_dependency("old").unwrap());
+        assert!(!editor.remove_dependency("missing").unwrap());
+        let mut package = toml::from_str::<PackageDetails>(
+            r#"version = "*"
+tarball = "https://example.com/json.tar.gz""#,
+        )
+        .unwrap();
+        package.version = Requirement::from_str("^0.2.0").unwrap();
+        editor.add_dependency("json", &package).unwrap();
+        editor.write().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"# the example package
+[package]
+name = "example"
+version = "0.1.0"
+
+[dependencies]
+# used by the server
+mqtt = { version = ">=0.1", git = "https://github.com/LF-Community/mqtt.git" }
+zlib = { version = "^1.3" }
+json = { version = "^0.2.0", tarball = "https://example.com/json.tar.gz" }
+
+[[app]]
+name = "server" # the main app
+target = "C"
+"#
+        );
+    }
+}
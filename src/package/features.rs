@@ -0,0 +1,227 @@
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::package::target_properties::{
+    LibraryTargetProperties, LibraryTargetPropertiesFile, MergeTargetProperties,
+};
+use crate::package::tree::PackageDetails;
+use crate::util::errors::LingoError;
+
+/// name of the feature that is enabled unless a dependant disables the default features
+pub const DEFAULT_FEATURE: &str = "default";
+
+/// An entry of the `[features]` table. A feature enables other features of the package,
+/// optional dependencies with `dep:<name>` and features of dependencies with
+/// `<dependency>/<feature>`. In its table form it can add target properties to the library.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(untagged)]
+pub enum Feature {
+    /// `tls = ["dep:mbedtls", "websocket/tls"]`
+    Enables(Vec<String>),
+    /// `tls = { enables = ["dep:mbedtls"], cmake-include = "tls.cmake", sources = [...] }`
+    Table {
+        #[serde(default)]
+        enables: Vec<String>,
+        #[serde(flatten)]
+        properties: LibraryTargetPropertiesFile,
+    },
+}
+
+impl Feature {
+    pub fn enables(&self) -> &[String] {
+        match self {
+            Feature::Enables(enables) => enables,
+            Feature::Table { enables, .. } => enables,
+        }
+    }
+
+    /// target properties that are added to the library while the feature is enabled
+    pub fn properties(&self) -> Option<&LibraryTargetPropertiesFile> {
+        match self {
+            Feature::Enables(_) => None,
+            Feature::Table { properties, .. } => Some(properties),
+        }
+    }
+}
+
+/// Features that the dependants of a package request from it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeatureRequest {
+    pub features: BTreeSet<String>,
+    /// the default feature is enabled unless every dependant disabled it
+    pub default: bool,
+}
+
+impl Default for FeatureRequest {
+    fn default() -> Self {
+        FeatureRequest {
+            features: BTreeSet::new(),
+            default: true,
+        }
+    }
+}
+
+impl FeatureRequest {
+    /// the features a single dependant requests
+    pub fn from_details(package: &PackageDetails) -> Self {
+        FeatureRequest {
+            features: package.features.iter().cloned().collect(),
+            default: package.default_features.unwrap_or(true),
+        }
+    }
+
+    /// Adds the features requested by another dependant, returns true if anything changed.
+    pub fn merge(&mut self, other: &FeatureRequest) -> bool {
+        let before = (self.features.len(), self.default);
+        self.features.extend(other.features.iter().cloned());
+        self.default |= other.default;
+        before != (self.features.len(), self.default)
+    }
+}
+
+/// Features of a package that are enabled together with everything they enable
+#[derive(Clone, Debug, Default)]
+pub struct EnabledFeatures {
+    pub features: BTreeSet<String>,
+    /// optional dependencies that are enabled
+    pub dependencies: BTreeSet<String>,
+    /// features that are enabled on the dependencies
+    pub dependency_features: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl EnabledFeatures {
+    /// Enables the requested features of the package `name` and everything they enable.
+    pub fn enable(
+        name: &str,
+        table: &BTreeMap<String, Feature>,
+        request: &FeatureRequest,
+    ) -> anyhow::Result<EnabledFeatures> {
+        let mut enabled = EnabledFeatures::default();
+        let mut pending = request.features.iter().cloned().collect::<Vec<_>>();
+        if request.default && table.contains_key(DEFAULT_FEATURE) {
+            pending.push(DEFAULT_FEATURE.to_string());
+        }
+
+        while let Some(entry) = pending.pop() {
+            if let Some(dependency) = entry.strip_prefix("dep:") {
+                enabled.dependencies.insert(dependency.to_string());
+            } else if let Some((dependency, feature)) = entry.split_once('/') {
+                // enabling a feature of an optional dependency enables the dependency as well
+                enabled.dependencies.insert(dependency.to_string());
+                enabled
+                    .dependency_features
+                    .entry(dependency.to_string())
+                    .or_default()
+                    .insert(feature.to_string());
+            } else {
+                let feature = table
+                    .get(&entry)
+                    .ok_or_else(|| LingoError::UnknownFeature(name.to_string(), entry.clone()))?;
+                if enabled.features.insert(entry.clone()) {
+                    pending.extend(feature.enables().iter().cloned());
+                }
+            }
+        }
+
+        Ok(enabled)
+    }
+
+    /// Removes the optional dependencies that are not enabled and passes the enabled features
+    /// on to the dependencies.
+    pub fn apply(
+        &self,
+        name: &str,
+        dependencies: &[(String, PackageDetails)],
+    ) -> anyhow::Result<Vec<(String, PackageDetails)>> {
+        if let Some(unknown) = self.dependencies.iter().find(|enabled| {
+            !dependencies
+                .iter()
+                .any(|(dependency, _)| dependency == *enabled)
+        }) {
+            return Err(
+                LingoError::UnknownFeature(name.to_string(), format!("dep:{unknown}")).into(),
+            );
+        }
+
+        Ok(dependencies
+            .iter()
+            .filter(|(dependency, details)| {
+                !details.optional || self.dependencies.contains(dependency)
+            })
+            .map(|(dependency, details)| {
+                let mut details = details.clone();
+                for feature in self
+                    .dependency_features
+                    .get(dependency)
+                    .into_iter()
+                    .flatten()
+                {
+                    if !details.features.contains(feature) {
+                        details.features.push(feature.clone());
+                    }
+                }
+                (dependency.clone(), details)
+            })
+            .collect())
+    }
+}
+
+/// Adds the target properties of the enabled features to the properties of the library that
+/// is located at `root`.
+pub fn add_feature_properties(
+    properties: &mut LibraryTargetProperties,
+    table: &BTreeMap<String, Feature>,
+    enabled: &[String],
+    root: &Path,
+) -> anyhow::Result<()> {
+    for feature in enabled {
+        if let Some(extra) = table.get(feature).and_then(Feature::properties) {
+            properties.merge(&extra.clone().from(root))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enables_features_transitively() {
+        let table = toml::from_str::<BTreeMap<String, Feature>>(
+            r#"
+default = ["json"]
+json = ["dep:cjson"]
+tls = { enables = ["json", "websocket/tls"], cmake-include = "tls.cmake" }
+"#,
+        )
+        .unwrap();
+        assert!(table["tls"].properties().is_some());
+
+        let request = FeatureRequest {
+            features: BTreeSet::from(["tls".to_string()]),
+            default: false,
+        };
+        let enabled = EnabledFeatures::enable("mqtt", &table, &request).unwrap();
+        assert_eq!(
+            enabled.features,
+            BTreeSet::from(["json".to_string(), "tls".to_string()])
+        );
+        assert_eq!(
+            enabled.dependencies,
+            BTreeSet::from(["cjson".to_string(), "websocket".to_string()])
+        );
+        assert_eq!(
+            enabled.dependency_features["websocket"],
+            BTreeSet::from(["tls".to_string()])
+        );
+
+        let unknown = FeatureRequest {
+            features: BTreeSet::from(["zip".to_string()]),
+            default: true,
+        };
+        assert!(EnabledFeatures::enable("mqtt", &table, &unknown).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use versions::{Requirement, Versioning};
+
+use crate::package::{lock::DependencyLock, Config};
+
+/// Resolved dependency graph of a package, used to print the dependency tree.
+pub struct DependencyGraph {
+    /// name of the root package
+    root: String,
+    /// version of the root package
+    root_version: Versioning,
+    /// selected version and source per package
+    packages: BTreeMap<String, (Versioning, String)>,
+    /// mapping from a package to its dependencies and the requirement it imposes on them
+    edges: BTreeMap<String, Vec<(String, Requirement)>>,
+}
+
+impl DependencyGraph {
+    /// Creates the graph from the selected packages of the lock file, the edges are the
+    /// dependencies recorded for every package in the lock file.
+    pub fn new(config: &Config, lock: &DependencyLock) -> anyhow::Result<DependencyGraph> {
+        let mut packages = BTreeMap::new();
+        let mut edges = BTreeMap::new();
+
+        let sorted = |dependencies: Vec<(String, Requirement)>| {
+            let mut dependencies = dependencies;
+            dependencies.sort_by(|(a, _), (b, _)| a.cmp(b));
+            dependencies
+        };
+
+        edges.insert(
+            config.package.name.clone(),
+            sorted(
+                config
+                    .enabled_dependencies()?
+                    .into_iter()
+                    .map(|(name, details)| (name, details.version))
+                    .collect(),
+            ),
+        );
+
+        for library in lock.libraries() {
+            let Some(package_lock) = lock.dependencies.get(&library.name) else {
+                continue;
+            };
+            packages.insert(
+                library.name.clone(),
+                (
+                    package_lock.version.clone(),
+                    package_lock.source.to_string(),
+                ),
+            );
+            edges.insert(
+                library.name.clone(),
+                sorted(
+                    package_lock
+                        .dependencies
+                        .iter()
+                        .map(|dependency| (dependency.name.clone(), dependency.requirement.clone()))
+                        .collect(),
+                ),
+            );
+        }
+
+        Ok(DependencyGraph {
+            root: config.package.name.clone(),
+            root_version: config.package.version.clone(),
+            packages,
+            edges,
+        })
+    }
+
+    fn label(&self, name: &str) -> String {
+        if name == self.root {
+            return format!("{} v{}", name, self.root_version);
+        }
+
+        match self.packages.get(name) {
+            Some((version, source)) => format!("{} v{} ({})", name, version, source),
+            None => format!("{} (not resolved)", name),
+        }
+    }
+
+    /// children of a node, either its dependencies or when inverted its dependants
+    fn children(&self, name: &str, invert: bool) -> Vec<String> {
+        if invert {
+            self.edges
+                .iter()
+                .filter(|(_, dependencies)| dependencies.iter().any(|(dep, _)| dep == name))
+                .map(|(dependant, _)| dependant.clone())
+                .collect()
+        } else {
+            self.edges
+                .get(name)
+                .map(|dependencies| dependencies.iter().map(|(dep, _)| dep.clone()).collect())
+                .unwrap_or_default()
+        }
+    }
+
+    /// Renders the tree starting at the root package or when `invert` is given the tree of all
+    /// the packages that pull in this package.
+    pub fn render_tree(&self, invert: Option<&str>, depth: Option<usize>) -> String {
+        let start = invert.unwrap_or(&self.root);
+        let mut output = self.label(start) + "\n";
+        let mut printed = BTreeSet::new();
+        let mut path = vec![start.to_string()];
+
+        self.render_children(
+            start,
+            invert.is_some(),
+            depth,
+            "",
+            &mut path,
+            &mut printed,
+            &mut output,
+        );
+        output
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_children(
+        &self,
+        name: &str,
+        invert: bool,
+        depth: Option<usize>,
+        prefix: &str,
+        path: &mut Vec<String>,
+        printed: &mut BTreeSet<String>,
+        output: &mut String,
+    ) {
+        if depth.is_some_and(|depth| path.len() > depth) {
+            return;
+        }
+
+        let children = self.children(name, invert);
+        for (index, child) in children.iter().enumerate() {
+            let last = index + 1 == children.len();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+
+            output.push_str(&format!("{}{}{}", prefix, branch, self.label(child)));
+
+            // packages that already have been expanded or that would form a cycle are marked
+            if path.contains(child) || !printed.insert(child.clone()) {
+                output.push_str(" (*)\n");
+                continue;
+            }
+            output.push('\n');
+
+            path.push(child.clone());
+            self.render_children(
+                child,
+                invert,
+                depth,
+                &format!("{}{}", prefix, indent),
+                path,
+                printed,
+                output,
+            );
+            path.pop();
+        }
+    }
+
+    /// Renders all packages that are required with different requirements by their dependants.
+    pub fn render_duplicates(&self) -> String {
+        let mut requirements = BTreeMap::<&String, Vec<(&String, &Requirement)>>::new();
+        for (dependant, dependencies) in &self.edges {
+            for (name, requirement) in dependencies {
+                requirements
+                    .entry(name)
+                    .or_default()
+                    .push((dependant, requirement));
+            }
+        }
+
+        let mut output = String::new();
+        for (name, requirements) in requirements {
+            let distinct = requirements
+                .iter()
+                .map(|(_, requirement)| requirement.to_string())
+                .collect::<BTreeSet<_>>();
+            if distinct.len() < 2 {
+                continue;
+            }
+
+            output.push_str(&self.label(name));
+            output.push('\n');
+            for (dependant, requirement) in requirements {
+                let satisfied = self
+                    .packages
+                    .get(name)
+                    .is_some_and(|(version, _)| requirement.matches(version));
+                output.push_str(&format!(
+                    "    {} required by {}{}\n",
+                    requirement,
+                    self.label(dependant),
+                    if satisfied { "" } else { " (not satisfied)" }
+                ));
+            }
+        }
+        output
+    }
+}
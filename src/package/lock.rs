@@ -1,33 +1,36 @@
-use crate::util::sha1dir;
+use crate::util::checksum;
 use colored::Colorize;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use versions::Versioning;
+use versions::{Requirement, Versioning};
 
 use log::error;
 use serde::de::Error as DeserializationError;
 use serde::ser::Error as SerializationError;
 use std::cmp::PartialEq;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Display;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use url::ParseError;
 
-use crate::GitCloneAndCheckoutCap;
+use crate::{DownloadCapability, GitCloneAndCheckoutCap};
 
-use crate::package::management::copy_dir_all;
+use crate::package::management::{copy_dir_all, FetchPolicy};
 use crate::package::{
-    deserialize_version, serialize_version,
+    deserialize_version,
+    features::add_feature_properties,
+    serialize_version,
     target_properties::{LibraryTargetProperties, MergeTargetProperties},
-    tree::{DependencyTreeNode, PackageDetails, ProjectSource},
-    ConfigFile,
+    tree::{DependencyTreeNode, PackageDetails, PatchDetails, ProjectSource},
+    App, ConfigFile,
 };
 use crate::util::errors::LingoError;
 
 pub struct ParseLockSourceError {}
 
 /// Different package sources types, available inside the lock file.
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum PackageLockSourceType {
     REGISTRY,
     GIT,
@@ -36,7 +39,7 @@ pub enum PackageLockSourceType {
 }
 
 /// Struct that saves the source uri string
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PackageLockSource {
     pub source_type: PackageLockSourceType,
     pub uri: String,
@@ -77,6 +80,7 @@ impl From<ProjectSource> for PackageLockSourceType {
             ProjectSource::Git(_) => Self::GIT,
             ProjectSource::TarBall(_) => Self::TARBALL,
             ProjectSource::Path(_) => Self::PATH,
+            ProjectSource::Registry(_) => Self::REGISTRY,
         }
     }
 }
@@ -115,7 +119,44 @@ impl FromStr for PackageLockSource {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+/// version of the Lingo.lock format that is written by this version of lingo
+pub const LOCK_FILE_VERSION: i64 = 2;
+
+/// A dependency of a locked package together with the requirement it was resolved with,
+/// written as `<name> <requirement>`
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockedDependency {
+    pub name: String,
+    pub requirement: Requirement,
+}
+
+impl Serialize for LockedDependency {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{} {}", self.name, self.requirement))
+    }
+}
+
+impl<'de> Deserialize<'de> for LockedDependency {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+        let (name, requirement) = s
+            .split_once(' ')
+            .ok_or(D::Error::custom("expected <name> <requirement>"))?;
+        Ok(LockedDependency {
+            name: name.to_string(),
+            requirement: Requirement::from_str(requirement)
+                .map_err(|_| D::Error::custom("cannot parse requirement"))?,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct PackageLock {
     pub name: String,
     #[serde(
@@ -125,6 +166,90 @@ pub struct PackageLock {
     pub version: Versioning,
     pub source: PackageLockSource,
     pub checksum: String,
+    /// sha256 of the archive for tarball packages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// packages this package depends on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<LockedDependency>,
+    /// the source was replaced by the `[patch]` table
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub patched: bool,
+    /// directory inside the git repository that contains the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shallow: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submodules: Option<bool>,
+    /// features of the package that are enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+}
+
+impl PackageLock {
+    /// the details required to fetch exactly the locked package again
+    pub fn details(&self) -> Result<PackageDetails, ParseError> {
+        let mut details = PackageDetails::try_from(&self.source)?;
+        details.sha256 = self.sha256.clone();
+        details.subdir = self.subdir.clone();
+        details.shallow = self.shallow;
+        details.submodules = self.submodules;
+        Ok(details)
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// On disk representation of the Lingo.lock, the packages are sorted by their name
+#[derive(Deserialize, Serialize)]
+struct LockFile {
+    version: i64,
+    #[serde(rename = "package", default)]
+    packages: Vec<PackageLock>,
+}
+
+/// Package inside a Lingo.lock that was written before the format was versioned. These lock
+/// files contain one table per package keyed by its name. The checksums were computed over
+/// the working directory instead of the package, hence they are dropped.
+#[derive(Deserialize)]
+struct LegacyPackageLock {
+    name: String,
+    #[serde(deserialize_with = "deserialize_version")]
+    version: Versioning,
+    source: PackageLockSource,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+impl From<LegacyPackageLock> for PackageLock {
+    fn from(value: LegacyPackageLock) -> Self {
+        PackageLock {
+            name: value.name,
+            version: value.version,
+            source: value.source,
+            checksum: String::new(),
+            sha256: value.sha256,
+            // the requirements were not recorded
+            dependencies: value
+                .dependencies
+                .into_iter()
+                .map(|name| LockedDependency {
+                    name,
+                    requirement: Requirement::default(),
+                })
+                .collect(),
+            patched: false,
+            subdir: None,
+            shallow: None,
+            submodules: None,
+            features: vec![],
+        }
+    }
 }
 
 impl From<DependencyTreeNode> for PackageLock {
@@ -132,7 +257,13 @@ impl From<DependencyTreeNode> for PackageLock {
         let uri = match &value.package.mutual_exclusive {
             ProjectSource::Git(git) => git.to_string(),
             ProjectSource::TarBall(tar) => tar.to_string(),
-            ProjectSource::Path(path) => format!("{:?}", path),
+            ProjectSource::Path(path) => path.display().to_string(),
+            ProjectSource::Registry(_) => value
+                .package
+                .tarball
+                .as_ref()
+                .map(|url| url.to_string())
+                .unwrap_or_default(),
         };
 
         PackageLock {
@@ -144,6 +275,13 @@ impl From<DependencyTreeNode> for PackageLock {
                 rev: value.package.git_rev,
             },
             checksum: value.hash,
+            sha256: value.package.sha256,
+            dependencies: vec![],
+            patched: false,
+            subdir: value.package.subdir,
+            shallow: value.package.shallow,
+            submodules: value.package.submodules,
+            features: value.features,
         }
     }
 }
@@ -161,79 +299,324 @@ impl<'de> Deserialize<'de> for PackageLockSource {
     }
 }
 
+impl PackageLockSource {
+    /// checks if the package was locked from the given source
+    pub fn matches(&self, source: &ProjectSource) -> bool {
+        match source {
+            ProjectSource::Git(url) => {
+                self.source_type == PackageLockSourceType::GIT && self.uri == url.to_string()
+            }
+            ProjectSource::TarBall(url) => {
+                self.source_type == PackageLockSourceType::TARBALL && self.uri == url.to_string()
+            }
+            ProjectSource::Path(path) => {
+                self.source_type == PackageLockSourceType::PATH
+                    && self.uri == path.display().to_string()
+            }
+            ProjectSource::Registry(_) => self.source_type == PackageLockSourceType::REGISTRY,
+        }
+    }
+}
+
+/// generates the source uri string following the pattern <type>+<url>(#<git-rev>)
+impl Display for PackageLockSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}+{}", self.source_type, self.uri)?;
+        if let Some(rev) = &self.rev {
+            write!(f, "#{}", rev)?;
+        }
+        Ok(())
+    }
+}
+
 impl Serialize for PackageLockSource {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let source_type = self.source_type.to_string();
-        let mut serialized_string = format!("{}+{}", source_type, self.uri);
-
-        if self.source_type == PackageLockSourceType::GIT {
-            if let Some(rev) = self.rev.clone() {
-                serialized_string = format!("{}#{}", serialized_string, rev)
-            } else {
-                error!("expected and revision but got none during serialization of lock file!");
-                return Err(S::Error::custom("expected revision but gone None"));
-            }
+        if self.source_type == PackageLockSourceType::GIT && self.rev.is_none() {
+            error!("expected and revision but got none during serialization of lock file!");
+            return Err(S::Error::custom("expected revision but gone None"));
         }
 
-        serializer.serialize_str(&serialized_string)
+        serializer.serialize_str(&self.to_string())
     }
 }
 
-#[derive(Deserialize, Serialize, Default, Debug)]
+#[derive(Default, Debug)]
 pub struct DependencyLock {
     /// mapping from package name to location
-    #[serde(flatten)]
     pub dependencies: HashMap<String, PackageLock>,
 
     /// this will be populated when the project is successfully loaded from the lock file
-    #[serde(skip)]
     loaded_dependencies: Vec<DependencyTreeNode>,
+
+    /// the lock was read from an older format and should be written again
+    migrated: bool,
 }
 
 impl DependencyLock {
-    pub(crate) fn create(selected_dependencies: Vec<DependencyTreeNode>) -> DependencyLock {
+    pub(crate) fn create(
+        selected_dependencies: Vec<DependencyTreeNode>,
+        edges: &BTreeMap<String, Vec<(String, PackageDetails)>>,
+    ) -> DependencyLock {
         let mut map = HashMap::new();
         for dependency in &selected_dependencies {
-            map.insert(
-                dependency.name.clone(),
-                PackageLock::from(dependency.clone()),
-            );
+            let mut lock = PackageLock::from(dependency.clone());
+            lock.dependencies = edges
+                .get(&dependency.name)
+                .into_iter()
+                .flatten()
+                .map(|(name, details)| LockedDependency {
+                    name: name.clone(),
+                    requirement: details.version.clone(),
+                })
+                .collect();
+            map.insert(dependency.name.clone(), lock);
         }
         Self {
             dependencies: map,
             loaded_dependencies: selected_dependencies,
+            migrated: false,
+        }
+    }
+
+    /// Reads the Lingo.lock, lock files written in an older format are migrated.
+    pub fn read(path: &Path) -> anyhow::Result<DependencyLock> {
+        let table = toml::from_str::<toml::Table>(&fs::read_to_string(path)?)?;
+
+        let (packages, migrated) = match table.get("version") {
+            Some(toml::Value::Integer(LOCK_FILE_VERSION)) => (
+                toml::Value::Table(table).try_into::<LockFile>()?.packages,
+                false,
+            ),
+            Some(toml::Value::Integer(version)) => {
+                return Err(LingoError::UnsupportedLockFileVersion(*version).into())
+            }
+            // unversioned lock files, a package may be called version as well
+            _ => (
+                table
+                    .into_iter()
+                    .map(|(_, package)| Ok(package.try_into::<LegacyPackageLock>()?.into()))
+                    .collect::<anyhow::Result<Vec<PackageLock>>>()?,
+                true,
+            ),
+        };
+
+        Ok(DependencyLock {
+            dependencies: packages
+                .into_iter()
+                .map(|package| (package.name.clone(), package))
+                .collect(),
+            loaded_dependencies: vec![],
+            migrated,
+        })
+    }
+
+    /// Writes the Lingo.lock, packages and their dependencies are sorted by name so the
+    /// file does not change as long as the resolution does not change.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let mut packages = self.dependencies.values().cloned().collect::<Vec<_>>();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        for package in packages.iter_mut() {
+            package.dependencies.sort_by(|a, b| a.name.cmp(&b.name));
         }
+
+        let lock_file = LockFile {
+            version: LOCK_FILE_VERSION,
+            packages,
+        };
+        fs::write(path, toml::to_string(&lock_file)?)?;
+        Ok(())
+    }
+
+    /// the lock was read from an older format and should be written again
+    pub fn migrated(&self) -> bool {
+        self.migrated
+    }
+
+    /// Checks that every direct dependency is locked to a version that satisfies its
+    /// requirement and comes from the requested source, and that the locked packages are
+    /// patched exactly like the `[patch]` table says.
+    pub fn satisfies(
+        &self,
+        dependencies: &[(String, PackageDetails)],
+        patches: &HashMap<String, PatchDetails>,
+    ) -> bool {
+        let direct = dependencies.iter().all(|(name, details)| {
+            self.dependencies.get(name).is_some_and(|lock| {
+                let source = match patches.get(name) {
+                    Some(patch) => &patch.mutual_exclusive,
+                    None => &details.mutual_exclusive,
+                };
+                let (subdir, submodules, sha256) = match patches.get(name) {
+                    Some(patch) => (&patch.subdir, patch.submodules, &patch.sha256),
+                    None => (&details.subdir, details.submodules, &details.sha256),
+                };
+                // hex digests are often written in upper case
+                let same_archive = sha256.as_ref().is_none_or(|expected| {
+                    lock.sha256
+                        .as_ref()
+                        .is_some_and(|locked| locked.eq_ignore_ascii_case(expected))
+                });
+                details.version.matches(&lock.version)
+                    && lock.source.matches(source)
+                    && same_archive
+                    && lock.subdir == *subdir
+                    && lock.submodules == submodules
+                    && details
+                        .features
+                        .iter()
+                        .all(|feature| lock.features.contains(feature))
+            })
+        });
+
+        let patched = self
+            .dependencies
+            .iter()
+            .all(|(name, lock)| match patches.get(name) {
+                Some(patch) => {
+                    lock.patched
+                        && lock.source.matches(&patch.mutual_exclusive)
+                        && lock.subdir == patch.subdir
+                        && lock.submodules == patch.submodules
+                }
+                None => !lock.patched,
+            });
+
+        direct && patched
+    }
+
+    /// Compares the selected packages of both locks, checksums are not taken into account.
+    /// Lock files in an older format did not record the requirements between the packages,
+    /// hence only the names of the dependencies are compared for them.
+    pub fn same_resolution(&self, other: &DependencyLock) -> bool {
+        let names = |lock: &PackageLock| {
+            lock.dependencies
+                .iter()
+                .map(|dependency| dependency.name.clone())
+                .collect::<BTreeSet<_>>()
+        };
+        let same_dependencies = |lock: &PackageLock, other_lock: &PackageLock| {
+            if self.migrated || other.migrated {
+                names(lock) == names(other_lock)
+            } else {
+                lock.dependencies == other_lock.dependencies
+            }
+        };
+
+        self.dependencies.len() == other.dependencies.len()
+            && self.dependencies.iter().all(|(name, lock)| {
+                other.dependencies.get(name).is_some_and(|other| {
+                    lock.version == other.version
+                        && lock.source.to_string() == other.source.to_string()
+                        && lock.sha256 == other.sha256
+                        && same_dependencies(lock, other)
+                        && lock.patched == other.patched
+                        && lock.subdir == other.subdir
+                        && lock.submodules == other.submodules
+                })
+            })
+    }
+
+    /// Builds the dependency tree of the loaded packages starting at the given root packages.
+    pub fn tree(&self, roots: &[String]) -> anyhow::Result<Vec<DependencyTreeNode>> {
+        let selected = self
+            .loaded_dependencies
+            .iter()
+            .map(|node| (node.name.clone(), node.clone()))
+            .collect::<BTreeMap<_, _>>();
+        let edges = self
+            .dependencies
+            .iter()
+            .map(|(name, lock)| {
+                (
+                    name.clone(),
+                    lock.dependencies
+                        .iter()
+                        .map(|dependency| dependency.name.clone())
+                        .collect(),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        roots
+            .iter()
+            .map(|root| DependencyTreeNode::build(root, &selected, &edges, &mut vec![]))
+            .collect()
     }
 
+    /// names of the locked packages the given root packages depend on, directly or transitively
+    fn reachable(&self, roots: &[String]) -> BTreeSet<String> {
+        let mut reachable = BTreeSet::new();
+        let mut pending = roots.to_vec();
+        while let Some(name) = pending.pop() {
+            if let Some(lock) = self.dependencies.get(&name) {
+                if reachable.insert(name) {
+                    pending.extend(lock.dependencies.iter().map(|dep| dep.name.clone()));
+                }
+            }
+        }
+        reachable
+    }
+
+    /// drops the loaded packages the given root packages do not depend on
+    pub(crate) fn retain_loaded(&mut self, roots: &[String]) {
+        let reachable = self.reachable(roots);
+        self.loaded_dependencies
+            .retain(|node| reachable.contains(&node.name));
+    }
+
+    /// Loads the packages needed by the given root packages from the library folder, packages
+    /// that are only used by other roots, e.g. the dev-dependencies of the tests, are neither
+    /// fetched nor loaded.
     pub fn init(
         &mut self,
-        lfc_include_folder: &Path,
+        roots: &[String],
+        library_path: &Path,
+        policy: FetchPolicy,
         git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCapability,
     ) -> anyhow::Result<()> {
-        for (_, lock) in self.dependencies.iter() {
-            let temp = lfc_include_folder.join(&lock.name);
-            // the Lingo.toml for this dependency doesnt exists, hence we need to fetch this package
-            if !temp.join("Lingo.toml").exists() {
-                let mut details = PackageDetails::try_from(&lock.source)?;
-
-                details
-                    .fetch(&temp, git_clone_and_checkout_cap)
-                    .expect("cannot pull package");
+        let reachable = self.reachable(roots);
+        for lock in self
+            .dependencies
+            .values_mut()
+            .filter(|lock| reachable.contains(&lock.name))
+        {
+            // packages are stored by their checksum, a package that is missing is fetched and
+            // moved to the place its content belongs to
+            let mut temp = library_path.join(&lock.checksum);
+            if lock.checksum.is_empty() || !temp.join("Lingo.toml").exists() {
+                let fetched = library_path.join("temporary");
+                let _ = fs::remove_dir_all(&fetched);
+                lock.details()?.fetch(
+                    &fetched,
+                    policy.offline,
+                    git_clone_and_checkout_cap,
+                    download_cap,
+                )?;
+
+                temp = library_path.join(checksum::checksum_dir(&fetched)?);
+                let _ = fs::remove_dir_all(&temp);
+                fs::rename(&fetched, &temp)?;
             }
 
-            let hash = sha1dir::checksum_current_dir(&temp, false);
-
-            if hash.to_string() != lock.checksum {
-                error!("checksum does not match aborting!");
+            // local path dependencies are expected to change, hence they are not verified
+            let hash = checksum::checksum_dir(&temp)?;
+            if hash != lock.checksum && lock.source.source_type != PackageLockSourceType::PATH {
+                let mismatch =
+                    LingoError::ChecksumMismatch(lock.name.clone(), lock.checksum.clone(), hash);
+                if !policy.ignore_checksums {
+                    return Err(mismatch.into());
+                }
+                error!("{}", mismatch);
             }
 
             let lingo_toml_text = fs::read_to_string(temp.join("Lingo.toml"))?;
             let read_toml = toml::from_str::<ConfigFile>(&lingo_toml_text)?.to_config(&temp);
 
-            println!(
+            eprintln!(
                 "{} {} ... {}",
                 "Reading".green().bold(),
                 lock.name,
@@ -251,48 +634,185 @@ impl DependencyLock {
                 }
             };
 
+            // the enabled features add their target properties to the library
+            let mut properties = lib.properties.clone();
+            add_feature_properties(&mut properties, &read_toml.features, &lock.features, &temp)?;
+
             self.loaded_dependencies.push(DependencyTreeNode {
-                name: read_toml.package.name.clone(),
+                name: lock.name.clone(),
                 version: read_toml.package.version.clone(),
                 package: PackageDetails {
                     version: Default::default(),
                     mutual_exclusive: ProjectSource::Path(PathBuf::new()),
                     git_tag: None,
                     git_rev: None,
+                    sha256: None,
+                    tarball: None,
+                    subdir: None,
+                    shallow: None,
+                    submodules: None,
+                    optional: false,
+                    features: vec![],
+                    default_features: None,
                 },
+                include_path: lib.location.strip_prefix(&temp)?.to_path_buf(),
                 location: temp.clone(),
-                include_path: lib.location.clone(),
                 hash: lock.checksum.clone(),
                 dependencies: vec![],
-                properties: lib.properties.clone(),
+                properties,
+                targets: lib.targets.clone(),
+                platforms: lib.platforms.clone(),
+                features: lock.features.clone(),
+                required_by: vec![],
             });
         }
 
         Ok(())
     }
 
-    pub fn create_library_folder(
-        &self,
-        source_path: &Path,
-        target_path: &PathBuf,
-    ) -> anyhow::Result<()> {
-        fs::create_dir_all(target_path)?;
-        for (_, dep) in self.dependencies.iter() {
-            let local_source = source_path.join(&dep.checksum);
-            let find_source = target_path.clone().join(&dep.name);
-            fs::create_dir_all(&find_source)?;
-            copy_dir_all(&local_source, &find_source)?;
+    /// Copies the reactors of every loaded library, which are inside the `location` of the
+    /// library, to `<include folder>/<name>`, where lfc looks up imports like `<name>/Foo.lf`.
+    pub fn create_library_folder(&self, include_folder: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(include_folder)?;
+        for library in &self.loaded_dependencies {
+            let reactors = library.location.join(&library.include_path);
+            // a library may only provide native code
+            if reactors.is_dir() {
+                copy_dir_all(&reactors, include_folder.join(&library.name))?;
+            }
         }
 
         Ok(())
     }
 
+    /// checks that the app can use every library it depends on, directly or transitively
+    pub fn check_compatibility(&self, app: &App) -> anyhow::Result<()> {
+        for library in &self.loaded_dependencies {
+            library.check_usable_by(
+                &format!("the app {}", app.name),
+                &[app.target],
+                &[app.platform],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// all libraries the project depends on, directly or transitively
+    pub fn libraries(&self) -> &[DependencyTreeNode] {
+        &self.loaded_dependencies
+    }
+
     pub fn aggregate_target_properties(&self) -> anyhow::Result<LibraryTargetProperties> {
         let mut i = LibraryTargetProperties::default();
         for tp in &self.loaded_dependencies {
-            i.merge(&tp.properties)?;
+            i.merge(&tp.properties.resolve_paths(&tp.location))?;
         }
 
         Ok(i)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_unversioned_lock_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Lingo.lock");
+        // written by lingo before the format was versioned, one table per package
+        fs::write(
+            &path,
+            r#"
+[sub]
+name = "sub"
+version = "0.2.0"
+source = "tar+https://example.com/sub.tar.gz"
+checksum = "3b85ec76be5f490defea060a50229a2d9d2b2bb0"
+
+[mylib]
+name = "mylib"
+version = "0.1.0"
+source = "git+https://example.com/mylib.git#abc"
+checksum = "666d887870a8c6c2631081be649f2e28664decac"
+"#,
+        )
+        .unwrap();
+
+        let lock = DependencyLock::read(&path).unwrap();
+        assert!(lock.migrated());
+        assert!(lock.dependencies["sub"].checksum.is_empty());
+    }
+
+    #[test]
+    fn requires_the_locked_archive_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Lingo.lock");
+        fs::write(
+            &path,
+            r#"version = 2
+
+[[package]]
+name = "mylib"
+version = "0.1.0"
+source = "tar+https://example.com/mylib.tar.gz"
+checksum = ""
+sha256 = "ab12"
+"#,
+        )
+        .unwrap();
+        let lock = DependencyLock::read(&path).unwrap();
+
+        let dependency = |sha256: Option<&str>| {
+            let mut text =
+                "version = \"*\"\ntarball = \"https://example.com/mylib.tar.gz\"\n".to_string();
+            if let Some(sha256) = sha256 {
+                text.push_str(&format!("sha256 = \"{sha256}\"\n"));
+            }
+            vec![(
+                "mylib".to_string(),
+                toml::from_str::<PackageDetails>(&text).unwrap(),
+            )]
+        };
+        assert!(lock.satisfies(&dependency(None), &HashMap::new()));
+        assert!(lock.satisfies(&dependency(Some("AB12")), &HashMap::new()));
+        assert!(!lock.satisfies(&dependency(Some("cd34")), &HashMap::new()));
+    }
+
+    #[test]
+    fn leaves_out_packages_of_other_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Lingo.lock");
+        fs::write(
+            &path,
+            r#"version = 2
+
+[[package]]
+name = "mylib"
+version = "0.1.0"
+source = "path+../mylib"
+checksum = ""
+dependencies = ["sub *"]
+
+[[package]]
+name = "sub"
+version = "0.2.0"
+source = "path+../sub"
+checksum = ""
+
+[[package]]
+name = "assert"
+version = "0.1.0"
+source = "path+../assert"
+checksum = ""
+"#,
+        )
+        .unwrap();
+
+        let lock = DependencyLock::read(&path).unwrap();
+        assert_eq!(
+            lock.reachable(&["mylib".to_string()]),
+            BTreeSet::from(["mylib".to_string(), "sub".to_string()])
+        );
+    }
+}
@@ -1,32 +1,194 @@
 use colored::Colorize;
-use log::error;
-use versions::{Requirement, Versioning};
+use versions::{Requirement, SemVer, Versioning};
 
-use crate::util::sha1dir;
-use crate::{GitCloneAndCheckoutCap, GitUrl};
-use std::collections::HashMap;
+use crate::args::AddArgs;
+use crate::util::archive::{self, ArchiveFormat};
+use crate::util::checksum;
+use crate::{DownloadCapability, GitCloneAndCheckoutCap, GitListTagsCapability, GitUrl};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::fs::File;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use url::{ParseError, Url};
 
+use crate::package::cache::Cache;
+use crate::package::features::{add_feature_properties, EnabledFeatures, Feature, FeatureRequest};
 use crate::package::lock::{PackageLockSource, PackageLockSourceType};
+use crate::package::registry::Registry;
+use crate::package::solver::{Candidate, PackageProvider, Solver};
 use crate::package::{
     lock::DependencyLock,
     target_properties::LibraryTargetProperties,
-    tree::{DependencyTreeNode, GitLock, PackageDetails, ProjectSource},
-    ConfigFile, LIBRARY_DIRECTORY,
+    tree::{DependencyTreeNode, GitLock, PackageDetails, PatchDetails, ProjectSource},
+    ConfigFile, INCLUDE_DIRECTORY, LIBRARY_DIRECTORY,
 };
 use crate::util::errors::LingoError;
 
+/// Restrictions on how dependencies are resolved and fetched
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FetchPolicy {
+    /// Lingo.lock has to exist and must not change
+    pub locked: bool,
+    /// nothing is fetched over the network, only the cache and local sources are used
+    pub offline: bool,
+    /// packages that do not match the checksum in Lingo.lock only cause a warning
+    pub ignore_checksums: bool,
+}
+
 #[derive(Default)]
 pub struct DependencyManager {
-    /// queue of packages that need processing
-    pulling_queue: Vec<(String, PackageDetails)>,
+    /// restrictions on resolving and fetching the dependencies
+    policy: FetchPolicy,
+    /// packages that are pinned to the source recorded in the lock file
+    pinned: HashMap<String, PackageDetails>,
+    /// replaced sources from the `[patch]` table, they take precedence over pinned packages
+    patches: HashMap<String, PatchDetails>,
+    /// registry indices that have been opened, by their location
+    registries: HashMap<String, Registry>,
+    /// tags of git repositories that are versions, by the url of the repository
+    tags: HashMap<String, Vec<(Versioning, String)>>,
+    /// packages that have been fetched, by their source
+    fetched: HashMap<String, FetchedPackage>,
+    /// features requested from every package by its dependants
+    requests: HashMap<String, FeatureRequest>,
     /// the flatten dependency tree with selected packages from the dependency tree
     lock: DependencyLock,
+    /// tree of the selected packages, starting at the direct dependencies
+    tree: Vec<DependencyTreeNode>,
+}
+
+/// a package that has been fetched together with the dependencies declared in its Lingo.toml
+pub(crate) struct FetchedPackage {
+    node: DependencyTreeNode,
+    dependencies: Vec<(String, PackageDetails)>,
+    features: BTreeMap<String, Feature>,
+}
+
+/// provides the solver with packages, fetching them when needed
+struct Fetcher<'a, 'b> {
+    manager: &'a mut DependencyManager,
+    library_path: PathBuf,
+    clone: &'a GitCloneAndCheckoutCap<'b>,
+    list_tags: &'a GitListTagsCapability<'b>,
+    download: &'a DownloadCapability<'b>,
+}
+
+impl PackageProvider for Fetcher<'_, '_> {
+    fn candidates(
+        &mut self,
+        name: &str,
+        package: &PackageDetails,
+    ) -> anyhow::Result<Vec<Candidate>> {
+        // patched and pinned packages keep the requirement of the dependant but are fetched
+        // from the patched source or the source that was recorded in the lock file
+        let package = match (
+            self.manager.patches.get(name),
+            self.manager.pinned.get(name),
+        ) {
+            (Some(patch), _) => patch.apply(package),
+            (None, Some(pinned)) => PackageDetails {
+                version: package.version.clone(),
+                ..pinned.clone()
+            },
+            (None, None) => package.clone(),
+        };
+
+        // the registry knows all versions without fetching them
+        if let (ProjectSource::Registry(location), None) =
+            (&package.mutual_exclusive, &package.tarball)
+        {
+            let registry = self.manager.registry(location.as_deref(), self.clone)?;
+            return registry
+                .versions(name)?
+                .into_iter()
+                .map(|entry| {
+                    Ok(Candidate {
+                        version: entry.version.clone(),
+                        package: PackageDetails {
+                            tarball: Some(registry.tarball_url(&entry)?),
+                            sha256: Some(entry.sha256),
+                            ..package.clone()
+                        },
+                    })
+                })
+                .collect();
+        }
+
+        // without a tag, branch or rev every tag that is a version is a candidate, so the
+        // newest tag that matches the requirement is checked out
+        if let (ProjectSource::Git(url), None) = (&package.mutual_exclusive, &package.git_tag) {
+            let tags = self.manager.tags(url, self.list_tags)?;
+            if !tags.is_empty() {
+                return Ok(tags
+                    .iter()
+                    .map(|(version, tag)| Candidate {
+                        version: version.clone(),
+                        package: PackageDetails {
+                            git_tag: Some(GitLock::Tag(tag.clone())),
+                            ..package.clone()
+                        },
+                    })
+                    .collect());
+            }
+        }
+
+        // all other sources provide exactly one version
+        let fetched = self.manager.fetch_once(
+            name,
+            &package,
+            &self.library_path,
+            self.clone,
+            self.download,
+        )?;
+        Ok(vec![Candidate {
+            version: fetched.node.version.clone(),
+            package,
+        }])
+    }
+
+    fn dependencies(
+        &mut self,
+        name: &str,
+        candidate: &Candidate,
+    ) -> anyhow::Result<Option<Vec<(String, PackageDetails)>>> {
+        // until the features requested from the package are known, the features of the first
+        // dependant are used
+        let request = self
+            .manager
+            .requests
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| FeatureRequest::from_details(&candidate.package));
+        let fetched = self.manager.fetch_once(
+            name,
+            &candidate.package,
+            &self.library_path,
+            self.clone,
+            self.download,
+        )?;
+
+        // a tag was selected by its name, a package that disagrees with it is not a candidate
+        if let (ProjectSource::Git(url), Some(GitLock::Tag(tag))) = (
+            &candidate.package.mutual_exclusive,
+            &candidate.package.git_tag,
+        ) {
+            if fetched.node.version != candidate.version {
+                log::warn!(
+                    "{}",
+                    LingoError::LingoVersionMismatch(format!(
+                        "{url} at tag {tag}, which declares version {}",
+                        fetched.node.version
+                    ))
+                );
+                return Ok(None);
+            }
+        }
+
+        // optional dependencies are only used when one of the enabled features asks for them
+        EnabledFeatures::enable(name, &fetched.features, &request)?
+            .apply(name, &fetched.dependencies)
+            .map(Some)
+    }
 }
 
 /// this copies all the files recursively from one location to another
@@ -53,15 +215,71 @@ impl TryFrom<&PackageLockSource> for PackageDetails {
         Ok(PackageDetails {
             version: Default::default(),
             mutual_exclusive: match value.source_type {
-                PackageLockSourceType::REGISTRY => {
-                    todo!()
-                }
+                PackageLockSourceType::REGISTRY => ProjectSource::Registry(None),
                 PackageLockSourceType::GIT => ProjectSource::Git(Url::from_str(url)?),
                 PackageLockSourceType::TARBALL => ProjectSource::TarBall(Url::from_str(url)?),
                 PackageLockSourceType::PATH => ProjectSource::Path(PathBuf::from(url)),
             },
             git_tag: value.rev.clone().map(GitLock::Rev),
             git_rev: value.rev.clone(),
+            sha256: None,
+            tarball: match value.source_type {
+                PackageLockSourceType::REGISTRY => Some(Url::from_str(url)?),
+                _ => None,
+            },
+            subdir: None,
+            shallow: None,
+            submodules: None,
+            optional: false,
+            features: vec![],
+            default_features: None,
+        })
+    }
+}
+
+impl TryFrom<&AddArgs> for PackageDetails {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &AddArgs) -> Result<Self, Self::Error> {
+        let mutual_exclusive = match (&value.git, &value.path, &value.tarball) {
+            (Some(git), _, _) => ProjectSource::Git(git.clone()),
+            (_, Some(path), _) => ProjectSource::Path(path.clone()),
+            (_, _, Some(tarball)) => ProjectSource::TarBall(tarball.clone()),
+            _ => ProjectSource::Registry(value.registry.clone()),
+        };
+
+        let git_tag = match (&value.tag, &value.branch, &value.rev) {
+            (Some(tag), _, _) => Some(GitLock::Tag(tag.clone())),
+            (_, Some(branch), _) => Some(GitLock::Branch(branch.clone())),
+            (_, _, Some(rev)) => Some(GitLock::Rev(rev.clone())),
+            _ => None,
+        };
+
+        if git_tag.is_some() && value.git.is_none() {
+            return Err(anyhow::anyhow!(
+                "--tag, --branch and --rev can only be used together with --git"
+            ));
+        }
+
+        if value.sha256.is_some() && value.tarball.is_none() {
+            return Err(anyhow::anyhow!(
+                "--sha256 can only be used together with --tarball"
+            ));
+        }
+
+        Ok(PackageDetails {
+            version: Requirement::from_str(value.version.as_deref().unwrap_or("*"))?,
+            mutual_exclusive,
+            git_tag,
+            git_rev: None,
+            sha256: value.sha256.clone(),
+            tarball: None,
+            subdir: None,
+            shallow: None,
+            submodules: None,
+            optional: false,
+            features: vec![],
+            default_features: None,
         })
     }
 }
@@ -71,160 +289,509 @@ impl PackageDetails {
     pub fn fetch(
         &mut self,
         library_path: &PathBuf,
+        offline: bool,
         clone: &GitCloneAndCheckoutCap,
+        download: &DownloadCapability,
     ) -> anyhow::Result<()> {
         match &self.mutual_exclusive {
             ProjectSource::Path(path_buf) => {
                 let src = fs::canonicalize(path_buf)?;
+                fs::create_dir_all(library_path)?;
                 let dst = fs::canonicalize(library_path)?;
                 Ok(copy_dir_all(src, dst)?)
             }
             ProjectSource::Git(git_url) => {
-                self.git_rev = clone(
-                    GitUrl::from(git_url.as_str()),
-                    library_path,
-                    self.git_tag.clone(),
-                )?;
+                let cache = Cache::open();
+
+                // only an exact revision can be looked up, branches and tags may have moved
+                if let (Some(cache), Some(GitLock::Rev(rev))) = (&cache, &self.git_tag) {
+                    if cache.restore(&self.git_cache_key(git_url, rev), library_path)? {
+                        self.git_rev = Some(rev.clone());
+                        return Ok(());
+                    }
+                }
+
+                if offline && git_url.scheme() != "file" {
+                    return Err(LingoError::OfflineFetch(git_url.to_string()).into());
+                }
+
+                match &self.subdir {
+                    // the whole repository is cloned, but only the subdirectory is kept
+                    Some(subdir) => {
+                        let checkout = tempfile::tempdir()?;
+                        self.git_rev = clone(
+                            GitUrl::from(git_url.as_str()),
+                            checkout.path(),
+                            self.git_tag.clone(),
+                            self.git_clone_options(),
+                        )?;
+
+                        let package_path = checkout.path().join(subdir);
+                        if !package_path.is_dir() {
+                            return Err(LingoError::MissingGitSubdirectory(
+                                git_url.to_string(),
+                                subdir.display().to_string(),
+                            )
+                            .into());
+                        }
+                        copy_dir_all(package_path, library_path)?;
+                    }
+                    None => {
+                        self.git_rev = clone(
+                            GitUrl::from(git_url.as_str()),
+                            library_path,
+                            self.git_tag.clone(),
+                            self.git_clone_options(),
+                        )?;
+                    }
+                }
+
+                if let (Some(cache), Some(rev)) = (&cache, &self.git_rev) {
+                    store_in_cache(cache, &self.git_cache_key(git_url, rev), library_path);
+                }
                 Ok(())
             }
-            _ => Ok(()),
+            ProjectSource::TarBall(url) => {
+                let url = url.clone();
+                self.fetch_tarball(&url, library_path, offline, download)
+            }
+            ProjectSource::Registry(_) => {
+                let url = self
+                    .tarball
+                    .clone()
+                    .ok_or(LingoError::NoRegistryConfigured)?;
+                self.fetch_tarball(&url, library_path, offline, download)
+            }
+        }
+    }
+}
+
+impl PackageDetails {
+    /// key of a git checkout inside the cache
+    fn git_cache_key(&self, url: &Url, rev: &str) -> String {
+        let parameters = self.content_parameters();
+        if parameters.is_empty() {
+            format!("git+{}#{}", url, rev)
+        } else {
+            format!("git+{}?{}#{}", url, parameters.join("&"), rev)
+        }
+    }
+
+    /// downloads and unpacks the archive and verifies its sha256 if one was specified
+    fn fetch_tarball(
+        &mut self,
+        url: &Url,
+        library_path: &Path,
+        offline: bool,
+        download: &DownloadCapability,
+    ) -> anyhow::Result<()> {
+        let format = url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .and_then(ArchiveFormat::from_file_name)
+            .ok_or(LingoError::UnsupportedArchiveFormat(url.to_string()))?;
+
+        let cache = Cache::open();
+        if let (Some(cache), Some(sha256)) = (&cache, &self.sha256) {
+            let key = format!("tar+{}#{}", url, sha256.to_ascii_lowercase());
+            if cache.restore(&key, library_path)? {
+                return Ok(());
+            }
+        }
+
+        // local archives are read in place, everything else is downloaded first
+        let download_dir = tempfile::tempdir()?;
+        let archive_path = if url.scheme() == "file" {
+            url.to_file_path()
+                .map_err(|_| LingoError::UnsupportedArchiveFormat(url.to_string()))?
+        } else if offline {
+            return Err(LingoError::OfflineFetch(url.to_string()).into());
+        } else {
+            let archive_path = download_dir.path().join("archive");
+            download(url, &archive_path)?;
+            archive_path
+        };
+
+        let sha256 = checksum::sha256_file(&archive_path)?;
+        if let Some(expected) = &self.sha256 {
+            // hex digests are often written in upper case
+            if !expected.eq_ignore_ascii_case(&sha256) {
+                return Err(LingoError::ArchiveChecksumMismatch(
+                    url.to_string(),
+                    expected.clone(),
+                    sha256,
+                )
+                .into());
+            }
+        }
+
+        fs::create_dir_all(library_path)?;
+        archive::unpack(&archive_path, format, library_path)?;
+
+        if let Some(cache) = &cache {
+            store_in_cache(cache, &format!("tar+{}#{}", url, sha256), library_path);
         }
+        self.sha256 = Some(sha256);
+        Ok(())
+    }
+}
+
+/// a package that cannot be cached is still usable, hence failures are only reported
+fn store_in_cache(cache: &Cache, key: &str, source: &Path) {
+    if let Err(e) = cache.store(key, source) {
+        log::error!("cannot store {} in the cache: {}", key, e);
     }
 }
 
 impl DependencyManager {
+    /// Resolves the `dependencies` into the Lingo.lock, but only the packages needed by the
+    /// `roots` are loaded, e.g. the dev-dependencies are locked but only loaded for the tests.
+    #[allow(clippy::too_many_arguments)]
     pub fn from_dependencies(
         dependencies: Vec<(String, PackageDetails)>,
+        roots: &[String],
+        patches: &HashMap<String, PatchDetails>,
         target_path: &Path,
+        policy: FetchPolicy,
         git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        list_tags_cap: &GitListTagsCapability,
+        download_cap: &DownloadCapability,
     ) -> anyhow::Result<DependencyManager> {
         // create library folder
         let library_path = target_path.join(LIBRARY_DIRECTORY);
         fs::create_dir_all(&library_path)?;
 
-        let mut manager;
-        let mut lock: DependencyLock;
         let lock_file = target_path.join("../Lingo.lock");
 
         // checks if a Lingo.lock file exists
         if lock_file.exists() {
             // reads and parses Lockfile
-            lock = toml::from_str::<DependencyLock>(&fs::read_to_string(lock_file)?)
-                .expect("cannot parse lock");
+            let mut lock = DependencyLock::read(&lock_file)?;
+
+            // if a lock file is present and still matches Lingo.toml it will load the
+            // dependencies from it and checks integrity of the build directory
+            // lock files in an older format do not record the dependencies between the
+            // packages nor usable checksums, they are resolved again with every package pinned
+            // to its locked revision, which writes both into the Lingo.lock
+            if lock.migrated() && lock.satisfies(&dependencies, patches) {
+                let mut manager = DependencyManager {
+                    policy,
+                    patches: patches.clone(),
+                    ..Default::default()
+                };
+                manager.pin(&lock, &[])?;
+                manager.resolve(
+                    dependencies,
+                    target_path,
+                    git_clone_and_checkout_cap,
+                    list_tags_cap,
+                    download_cap,
+                )?;
+                manager.retain_loaded(roots);
+                return Ok(manager);
+            }
+
+            if lock.satisfies(&dependencies, patches) {
+                lock.init(
+                    roots,
+                    &library_path,
+                    policy,
+                    git_clone_and_checkout_cap,
+                    download_cap,
+                )?;
+
+                let include_folder = target_path.join(INCLUDE_DIRECTORY);
+                let _ = fs::remove_dir_all(&include_folder);
+                lock.create_library_folder(&include_folder)?;
 
-            // if a lock file is present it will load the dependencies from it and checks
-            // integrity of the build directory
-            if let Ok(()) = lock.init(&target_path.join("lfc_include"), git_clone_and_checkout_cap)
-            {
                 return Ok(DependencyManager {
-                    pulling_queue: vec![],
+                    policy,
+                    tree: lock.tree(roots)?,
                     lock,
+                    ..Default::default()
                 });
             }
         }
 
         // creates a new dependency manager object
-        manager = DependencyManager::default();
-
-        // starts recursively pulling dependencies
-        let root_nodes = manager.pull(
-            dependencies.clone(),
+        let mut manager = DependencyManager {
+            policy,
+            patches: patches.clone(),
+            ..Default::default()
+        };
+        manager.resolve(
+            dependencies,
             target_path,
             git_clone_and_checkout_cap,
+            list_tags_cap,
+            download_cap,
         )?;
+        manager.retain_loaded(roots);
 
-        // flattens the dependency tree and makes the package selection
-        let selection = DependencyManager::flatten(root_nodes.clone())?;
-
-        // creates a lock file struct from the selected packages
-        lock = DependencyLock::create(selection);
+        Ok(manager)
+    }
 
-        // writes the lock file down
-        let mut lock_file = File::create(target_path.join("../Lingo.lock"))?;
+    /// Re-resolves the dependencies while ignoring the existing Lingo.lock. If `packages` is
+    /// not empty only the listed packages are updated, every other package stays pinned to
+    /// the revision recorded in the lock file.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        dependencies: Vec<(String, PackageDetails)>,
+        patches: &HashMap<String, PatchDetails>,
+        target_path: &Path,
+        packages: &[String],
+        policy: FetchPolicy,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        list_tags_cap: &GitListTagsCapability,
+        download_cap: &DownloadCapability,
+    ) -> anyhow::Result<DependencyManager> {
+        let mut manager = DependencyManager {
+            policy,
+            patches: patches.clone(),
+            ..Default::default()
+        };
+        let lock_file = target_path.join("../Lingo.lock");
 
-        println!("{:?}", lock.dependencies);
-        let serialized_toml = toml::to_string(&lock).expect("cannot generate toml");
+        // without a lock file there is nothing to pin
+        if !packages.is_empty() && lock_file.exists() {
+            let lock = DependencyLock::read(&lock_file)?;
 
-        lock_file.write_all(serialized_toml.as_ref())?;
+            let unknown_names = packages
+                .iter()
+                .filter(|&name| {
+                    !lock.dependencies.contains_key(name)
+                        && !dependencies.iter().any(|(dep, _)| dep == name)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            if !unknown_names.is_empty() {
+                return Err(LingoError::UnknownDependencyNames(unknown_names).into());
+            }
 
-        // moves the selected packages into the include folder
-        let include_folder = target_path.join("lfc_include");
-        lock.create_library_folder(&library_path, &include_folder)
-            .expect("creating lock folder failed");
+            manager.pin(&lock, packages)?;
+        }
 
-        // saves the lockfile with the dependency manager
-        manager.lock = lock;
+        fs::create_dir_all(target_path.join(LIBRARY_DIRECTORY))?;
+        manager.resolve(
+            dependencies,
+            target_path,
+            git_clone_and_checkout_cap,
+            list_tags_cap,
+            download_cap,
+        )?;
 
         Ok(manager)
     }
 
-    pub fn pull(
+    /// packages that are only used by other roots stay in the Lingo.lock but are not loaded
+    fn retain_loaded(&mut self, roots: &[String]) {
+        self.lock.retain_loaded(roots);
+        self.tree.retain(|node| roots.contains(&node.name));
+    }
+
+    /// pins every locked package except the listed ones to the revision in the lock file
+    fn pin(&mut self, lock: &DependencyLock, except: &[String]) -> anyhow::Result<()> {
+        // patched packages are not pinned, so removing a patch restores the original source
+        for (name, package_lock) in lock.dependencies.iter() {
+            if !except.contains(name) && !package_lock.patched {
+                self.pinned.insert(name.clone(), package_lock.details()?);
+            }
+        }
+        Ok(())
+    }
+
+    /// solves the dependencies, writes the Lingo.lock and populates the lfc include folder
+    /// with the selected packages
+    fn resolve(
         &mut self,
-        mut dependencies: Vec<(String, PackageDetails)>,
-        root_path: &Path,
+        dependencies: Vec<(String, PackageDetails)>,
+        target_path: &Path,
         git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
-    ) -> anyhow::Result<Vec<DependencyTreeNode>> {
-        let mut sub_dependencies = vec![];
-        self.pulling_queue.append(&mut dependencies);
-        let sub_dependency_path = root_path.join("libraries");
-        //fs::remove_dir_all(&sub_dependency_path)?;
-        fs::create_dir_all(&sub_dependency_path)?;
-
-        while !self.pulling_queue.is_empty() {
-            if let Some((package_name, package_details)) = self.pulling_queue.pop() {
-                print!("{} {} ...", "Cloning".green().bold(), package_name);
-                let node = match self.non_recursive_fetching(
-                    &package_name,
-                    package_details,
-                    &sub_dependency_path,
-                    git_clone_and_checkout_cap,
-                ) {
-                    Ok(value) => value,
-                    Err(e) => {
-                        return Err(e);
-                    }
+        list_tags_cap: &GitListTagsCapability,
+        download_cap: &DownloadCapability,
+    ) -> anyhow::Result<()> {
+        let library_path = target_path.join(LIBRARY_DIRECTORY);
+        fs::create_dir_all(&library_path)?;
+
+        let mut roots = dependencies
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        roots.sort();
+
+        // Selects one version per package, the packages are fetched on demand. The features of a
+        // package decide which of its optional dependencies are used, but they are only known
+        // once all of its dependants are selected. Features only get added, hence solving is
+        // repeated until the features requested from every package are stable.
+        let solution = loop {
+            let mut fetcher = Fetcher {
+                manager: self,
+                library_path: library_path.clone(),
+                clone: git_clone_and_checkout_cap,
+                list_tags: list_tags_cap,
+                download: download_cap,
+            };
+            let solution = match Solver::new(&mut fetcher)
+                .solve("Lingo.toml", dependencies.clone())?
+            {
+                Ok(solution) => solution,
+                Err(conflict) => {
+                    return Err(LingoError::UnsatisfiableRequirements(conflict.to_string()).into())
+                }
+            };
+
+            let mut changed = false;
+            for (name, candidate) in &solution.selected {
+                let mut request = FeatureRequest {
+                    features: Default::default(),
+                    default: false,
                 };
+                for requirement in &solution.requirements[name] {
+                    request.merge(&FeatureRequest::from_details(&requirement.package));
+                }
+
+                match self.requests.get_mut(name) {
+                    Some(existing) => changed |= existing.merge(&request),
+                    None => {
+                        changed |= request != FeatureRequest::from_details(&candidate.package);
+                        self.requests.insert(name.clone(), request);
+                    }
+                }
+            }
+
+            if !changed {
+                break solution;
+            }
+        };
+
+        let selected = solution
+            .selected
+            .iter()
+            .map(|(name, candidate)| {
+                let fetched = &self.fetched[&candidate.package.source_id()];
+                let mut node = fetched.node.clone().with_name(name);
+
+                // the enabled features add their target properties to the library
+                let enabled =
+                    EnabledFeatures::enable(name, &fetched.features, &self.requests[name])?;
+                node.features = enabled.features.into_iter().collect();
+                add_feature_properties(
+                    &mut node.properties,
+                    &fetched.features,
+                    &node.features,
+                    &node.location,
+                )?;
+
+                node.required_by = solution.requirements[name]
+                    .iter()
+                    .map(|requirement| {
+                        (
+                            requirement.dependant.clone(),
+                            requirement.package.version.clone(),
+                        )
+                    })
+                    .collect();
+                Ok((name.clone(), node))
+            })
+            .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+        let edges = solution
+            .dependencies
+            .iter()
+            .map(|(name, dependencies)| {
+                (
+                    name.clone(),
+                    dependencies.iter().map(|(dep, _)| dep.clone()).collect(),
+                )
+            })
+            .collect::<BTreeMap<_, Vec<_>>>();
 
-                sub_dependencies.push(node);
-            } else {
-                break;
+        // connects the selected packages to a tree, this fails for cyclic dependencies
+        self.tree = roots
+            .iter()
+            .map(|root| DependencyTreeNode::build(root, &selected, &edges, &mut vec![]))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // libraries have to be usable from the libraries that depend on them, the apps of the
+        // project are checked before their properties are merged
+        for (name, dependencies) in edges.iter() {
+            let Some(dependant) = selected.get(name) else {
+                continue;
+            };
+            for dependency in dependencies {
+                selected[dependency].check_usable_by(
+                    &format!("the library {name}"),
+                    &dependant.targets,
+                    &dependant.platforms,
+                )?;
             }
         }
 
-        //dependencies
-        Ok(sub_dependencies)
+        // creates a lock file struct from the selected packages
+        let mut lock =
+            DependencyLock::create(selected.into_values().collect(), &solution.dependencies);
+        for (name, package) in lock.dependencies.iter_mut() {
+            package.patched = self.patches.contains_key(name);
+        }
+        let lock_path = target_path.join("../Lingo.lock");
+
+        // a locked build may only reproduce the existing lock file
+        if self.policy.locked {
+            let existing = DependencyLock::read(&lock_path).ok();
+            if !existing.is_some_and(|existing| existing.same_resolution(&lock)) {
+                return Err(LingoError::LockFileOutdated.into());
+            }
+        }
+
+        // writes the lock file down
+        lock.write(&lock_path)?;
+
+        // copies the reactors of the selected packages into the include folder, packages that
+        // are no longer selected are removed from it
+        let include_folder = target_path.join(INCLUDE_DIRECTORY);
+        let _ = fs::remove_dir_all(&include_folder);
+        lock.create_library_folder(&include_folder)?;
+
+        // saves the lockfile with the dependency manager
+        self.lock = lock;
+
+        Ok(())
     }
 
+    /// fetches the package into the library folder and reads its Lingo.toml
     pub(crate) fn non_recursive_fetching(
         &mut self,
         name: &str,
         mut package: PackageDetails,
         base_path: &Path,
         git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
-    ) -> anyhow::Result<DependencyTreeNode> {
+        download_cap: &DownloadCapability,
+    ) -> anyhow::Result<FetchedPackage> {
         // creating the directory where the library will be housed
-        let library_path = base_path; //.join("libs");
-                                      // place where to drop the source
+        let library_path = base_path;
+        // place where to drop the source
         let temporary_path = library_path.join("temporary");
         let _ = fs::remove_dir_all(&temporary_path);
-        let _ = fs::create_dir_all(&temporary_path);
-
-        // directory where the dependencies will be dropped
 
         // creating the necessary directories
         fs::create_dir_all(library_path)?;
         fs::create_dir_all(&temporary_path)?;
 
         // cloning the specified package
-        package.fetch(&temporary_path, git_clone_and_checkout_cap)?;
+        eprint!("{} {} ...", "Cloning".green().bold(), name);
+        package.fetch(
+            &temporary_path,
+            self.policy.offline,
+            git_clone_and_checkout_cap,
+            download_cap,
+        )?;
 
-        let hash = sha1dir::checksum_current_dir(&temporary_path, false);
-        let include_path = library_path.join(hash.to_string());
+        let hash = checksum::checksum_dir(&temporary_path)?;
+        let include_path = library_path.join(&hash);
 
         let lingo_toml_text = fs::read_to_string(temporary_path.clone().join("Lingo.toml"))?;
         let read_toml = toml::from_str::<ConfigFile>(&lingo_toml_text)?.to_config(&temporary_path);
 
-        println!(" {}", read_toml.package.version);
+        eprintln!(" {}", read_toml.package.version);
 
         let config = match read_toml.library {
             Some(value) => value,
@@ -236,121 +803,613 @@ impl DependencyManager {
             }
         };
 
-        if !package.version.matches(&read_toml.package.version) {
-            error!("version mismatch between specified location and requested version requirement");
-            return Err(LingoError::LingoVersionMismatch(format!(
-                "requested version {} got version {}",
-                package.version, read_toml.package.version
-            ))
-            .into());
+        fs::create_dir_all(&include_path)?;
+        copy_dir_all(&temporary_path, &include_path)?;
+
+        let mut dependencies = Vec::from_iter(read_toml.dependencies);
+        dependencies.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        // relative paths inside a local package are relative to this package
+        if let ProjectSource::Path(package_path) = &package.mutual_exclusive {
+            for (_, dependency) in dependencies.iter_mut() {
+                if let ProjectSource::Path(path) = &mut dependency.mutual_exclusive {
+                    if path.is_relative() {
+                        *path = package_path.join(&path);
+                    }
+                }
+            }
         }
 
-        let dependencies = vec![];
+        Ok(FetchedPackage {
+            node: DependencyTreeNode {
+                name: name.to_string(),
+                package: package.clone(),
+                location: include_path.clone(),
+                include_path: config.location.strip_prefix(&temporary_path)?.to_path_buf(),
+                dependencies: vec![],
+                hash,
+                version: read_toml.package.version.clone(),
+                properties: config.properties,
+                targets: config.targets,
+                platforms: config.platforms,
+                features: vec![],
+                required_by: vec![],
+            },
+            dependencies,
+            features: read_toml.features,
+        })
+    }
 
-        for dep in read_toml.dependencies {
-            self.pulling_queue.push(dep);
+    /// fetches the package once, subsequent calls are served from the already fetched packages
+    fn fetch_once(
+        &mut self,
+        name: &str,
+        package: &PackageDetails,
+        library_path: &Path,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCapability,
+    ) -> anyhow::Result<&FetchedPackage> {
+        let source_id = package.source_id();
+        if !self.fetched.contains_key(&source_id) {
+            let fetched = self.non_recursive_fetching(
+                name,
+                package.clone(),
+                library_path,
+                git_clone_and_checkout_cap,
+                download_cap,
+            )?;
+            self.fetched.insert(source_id.clone(), fetched);
         }
 
-        fs::create_dir_all(&include_path)?;
-        copy_dir_all(&temporary_path, &include_path)?;
+        Ok(&self.fetched[&source_id])
+    }
+
+    /// Tags of the git repository that are versions like `v1.2.3` or `1.2.3`, they are listed
+    /// on first use.
+    fn tags(
+        &mut self,
+        url: &Url,
+        list_tags_cap: &GitListTagsCapability,
+    ) -> anyhow::Result<&[(Versioning, String)]> {
+        let key = url.to_string();
+        if !self.tags.contains_key(&key) {
+            if self.policy.offline && url.scheme() != "file" {
+                return Err(LingoError::OfflineFetch(key).into());
+            }
+
+            let tags = list_tags_cap(GitUrl::from(key.as_str()))?
+                .into_iter()
+                .filter_map(|tag| {
+                    let version = tag.strip_prefix('v').unwrap_or(&tag);
+                    SemVer::new(version).map(|version| (Versioning::Ideal(version), tag.clone()))
+                })
+                .collect();
+            self.tags.insert(key.clone(), tags);
+        }
+
+        Ok(&self.tags[&key])
+    }
+
+    /// the registry at the given location, it is opened on first use
+    fn registry(
+        &mut self,
+        location: Option<&str>,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+    ) -> anyhow::Result<&Registry> {
+        let location = Registry::location(location)?;
+        if !self.registries.contains_key(&location) {
+            let registry =
+                Registry::open(&location, self.policy.offline, git_clone_and_checkout_cap)?;
+            self.registries.insert(location.clone(), registry);
+        }
+
+        Ok(&self.registries[&location])
+    }
+
+    /// dependency tree, one node per direct dependency
+    pub fn tree(&self) -> &[DependencyTreeNode] {
+        &self.tree
+    }
+
+    pub fn lock(&self) -> &DependencyLock {
+        &self.lock
+    }
+
+    pub fn get_target_properties(&self) -> anyhow::Result<LibraryTargetProperties> {
+        self.lock.aggregate_target_properties()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::cache::CACHE_ENV_VARIABLE;
+    use crate::GitCloneError;
+    use std::sync::Once;
+
+    /// keeps the packages fetched by the tests out of the cache of the user
+    fn isolate_cache() {
+        static ISOLATE: Once = Once::new();
+        ISOLATE.call_once(|| {
+            let cache =
+                std::env::temp_dir().join(format!("lingo-test-cache-{}", std::process::id()));
+            std::env::set_var(CACHE_ENV_VARIABLE, cache);
+        });
+    }
+
+    /// writes a C library with the given `[dependencies]` table into the directory
+    fn library(dir: &Path, name: &str, version: &str, dependencies: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            dir.join("Lingo.toml"),
+            format!(
+                "[package]\nname = \"{name}\"\nversion = \"{version}\"\n\n[lib]\nname = \"{name}\"\nlocation = \".\"\ntarget = \"C\"\nplatform = \"Native\"\n\n[lib.properties]\n\n[dependencies]\n{dependencies}"
+            ),
+        )
+        .unwrap();
+    }
+
+    fn dependencies(text: &str) -> Vec<(String, PackageDetails)> {
+        let mut dependencies = toml::from_str::<HashMap<String, PackageDetails>>(text)
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>();
+        dependencies.sort_by(|(a, _), (b, _)| a.cmp(b));
+        dependencies
+    }
+
+    /// A git repository is faked by a directory with one subdirectory per tag and one called
+    /// `HEAD` for the default branch, the tag is used as the revision as well.
+    fn git_clone() -> GitCloneAndCheckoutCap<'static> {
+        Box::new(|url, destination, lock, _| {
+            let repository = Url::parse(url.into())
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or(GitCloneError("not a local repository".into()))?;
+            let tag = match lock {
+                Some(GitLock::Tag(tag)) | Some(GitLock::Rev(tag)) => tag,
+                Some(GitLock::Branch(_)) => return Err(GitCloneError("no branches".into())),
+                None => "HEAD".to_string(),
+            };
+            copy_dir_all(repository.join(&tag), destination)
+                .map_err(|e| GitCloneError(e.to_string()))?;
+            Ok(Some(tag))
+        })
+    }
 
-        Ok(DependencyTreeNode {
-            name: name.to_string(),
-            package: package.clone(),
-            location: include_path.clone(),
-            include_path: config.location.clone(),
-            dependencies: dependencies.clone(),
-            hash: hash.to_string(),
-            version: read_toml.package.version.clone(),
-            properties: config.properties,
+    fn git_list_tags() -> GitListTagsCapability<'static> {
+        Box::new(|url| {
+            let repository = Url::parse(url.into())
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or(GitCloneError("not a local repository".into()))?;
+            let entries = fs::read_dir(repository).map_err(|e| GitCloneError(e.to_string()))?;
+            Ok(entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect())
         })
     }
 
-    fn flatten(root_nodes: Vec<DependencyTreeNode>) -> anyhow::Result<Vec<DependencyTreeNode>> {
-        // implementation idea:
-        // 1.   we collect all the version requirements for packages => are the different
-        //      constraints satisfiable ?
-        // 2.   we collect all the different sources
-        // 3.   finding the set of sources that satisfies the set of version constraints
-        // 4.   pick the newest version from that set
-        // TODO: later we can probably do this in one pass
+    fn no_download() -> DownloadCapability<'static> {
+        Box::new(|_, _| Err(crate::DownloadError("offline".into())))
+    }
+
+    fn resolve(
+        project: &Path,
+        dependencies: Vec<(String, PackageDetails)>,
+        patches: &HashMap<String, PatchDetails>,
+        policy: FetchPolicy,
+    ) -> anyhow::Result<DependencyManager> {
+        let roots = dependencies
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        DependencyManager::from_dependencies(
+            dependencies,
+            &roots,
+            patches,
+            &project.join("build"),
+            policy,
+            &git_clone(),
+            &git_list_tags(),
+            &no_download(),
+        )
+    }
+
+    /// packs the directory into a `.tar.gz` with a single top-level directory and returns the
+    /// sha256 of the archive
+    fn archive(archive: &Path, package: &Path) -> String {
+        let file = fs::File::create(archive).unwrap();
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        ));
+        builder.append_dir_all("package", package).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        checksum::sha256_file(archive).unwrap()
+    }
+
+    fn locked_version(project: &Path, name: &str) -> String {
+        DependencyLock::read(&project.join("Lingo.lock"))
+            .unwrap()
+            .dependencies[name]
+            .version
+            .to_string()
+    }
 
-        let mut constraints = HashMap::<&String, Vec<Requirement>>::new();
-        let mut sources = HashMap::<&String, Vec<&DependencyTreeNode>>::new();
+    #[test]
+    fn update_moves_only_the_listed_packages() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let repositories = dir.path().join("repositories");
+        library(&repositories.join("sub/1.0.0"), "sub", "1.0.0", "");
+        library(&repositories.join("other/1.0.0"), "other", "1.0.0", "");
+        fs::create_dir_all(&project).unwrap();
 
-        // this basically flattens the
-        let mut nodes = Vec::new();
-        for node in root_nodes {
-            let mut children = node.aggregate();
-            nodes.append(&mut children);
+        let url = Url::from_directory_path(&repositories).unwrap();
+        let direct = dependencies(&format!(
+            "sub = {{ version = \"^1.0.0\", git = \"{url}sub\" }}\nother = {{ version = \"^1.0.0\", git = \"{url}other\" }}\n"
+        ));
+        resolve(
+            &project,
+            direct.clone(),
+            &HashMap::new(),
+            FetchPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(locked_version(&project, "sub"), "1.0.0");
+        assert_eq!(locked_version(&project, "other"), "1.0.0");
+
+        // both packages publish a new version, but only sub is updated
+        library(&repositories.join("sub/1.1.0"), "sub", "1.1.0", "");
+        library(&repositories.join("other/1.1.0"), "other", "1.1.0", "");
+        DependencyManager::update(
+            direct,
+            &HashMap::new(),
+            &project.join("build"),
+            &["sub".to_string()],
+            FetchPolicy::default(),
+            &git_clone(),
+            &git_list_tags(),
+            &no_download(),
+        )
+        .unwrap();
+        assert_eq!(locked_version(&project, "sub"), "1.1.0");
+        assert_eq!(locked_version(&project, "other"), "1.0.0");
+    }
+
+    #[test]
+    fn resolves_from_a_local_registry() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let registry = dir.path().join("registry");
+        fs::create_dir_all(registry.join("archives")).unwrap();
+        fs::create_dir_all(&project).unwrap();
+
+        let mut index = String::new();
+        for version in ["0.1.0", "0.1.1", "0.2.0"] {
+            let package = dir.path().join(format!("mylib-{version}"));
+            library(&package, "mylib", version, "");
+            let sha256 = archive(
+                &registry.join(format!("archives/mylib-{version}.tar.gz")),
+                &package,
+            );
+            index.push_str(&format!(
+                "[[version]]\nversion = \"{version}\"\ntarball = \"archives/mylib-{version}.tar.gz\"\nsha256 = \"{sha256}\"\n\n"
+            ));
         }
+        fs::write(registry.join("mylib.toml"), index).unwrap();
 
-        for node in &nodes {
-            let constraint = &node.package.version;
+        let manager = resolve(
+            &project,
+            dependencies(&format!(
+                "mylib = {{ version = \"^0.1.0\", registry = \"{}\" }}\n",
+                registry.display()
+            )),
+            &HashMap::new(),
+            FetchPolicy::default(),
+        )
+        .unwrap();
 
-            constraints
-                .entry(&node.name)
-                .and_modify(|value| {
-                    value.push(constraint.clone());
-                })
-                .or_insert(vec![constraint.clone()]);
+        // the newest matching version is fetched from the archive the index points to
+        let lock = &DependencyLock::read(&project.join("Lingo.lock"))
+            .unwrap()
+            .dependencies["mylib"];
+        assert_eq!(lock.version.to_string(), "0.1.1");
+        assert_eq!(lock.source.source_type, PackageLockSourceType::REGISTRY);
+        assert!(lock.source.uri.ends_with("archives/mylib-0.1.1.tar.gz"));
+        assert!(lock.sha256.is_some());
+        assert!(manager.lock().libraries()[0]
+            .location
+            .join("Lingo.toml")
+            .is_file());
+    }
 
-            sources
-                .entry(&node.name)
-                .and_modify(move |value| {
-                    value.push(node);
-                })
-                .or_insert(vec![&node]);
+    fn is_error(result: anyhow::Result<DependencyManager>, expected: LingoError) -> bool {
+        match result {
+            Ok(_) => false,
+            Err(error) => {
+                error.downcast_ref::<LingoError>().map(ToString::to_string)
+                    == Some(expected.to_string())
+            }
         }
+    }
 
-        let merged: Vec<(&String, Vec<Requirement>, Vec<&DependencyTreeNode>)> = constraints
-            .into_iter()
-            .filter_map(move |(key, requirements)| {
-                sources
-                    .get_mut(&key)
-                    .map(move |location| (key, requirements, location.clone()))
-            })
-            .collect();
+    #[test]
+    fn locked_requires_an_unchanged_lock_file() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        library(&dir.path().join("mylib"), "mylib", "0.1.0", "");
+        library(&dir.path().join("other"), "other", "0.1.0", "");
+        fs::create_dir_all(&project).unwrap();
+        let locked = FetchPolicy {
+            locked: true,
+            ..Default::default()
+        };
+        let mylib = dependencies(&format!(
+            "mylib = {{ version = \"*\", path = \"{}\" }}\n",
+            dir.path().join("mylib").display()
+        ));
 
-        let mut selection = Vec::new();
+        // without a lock file there is nothing to reproduce
+        assert!(is_error(
+            resolve(&project, mylib.clone(), &HashMap::new(), locked),
+            LingoError::LockFileOutdated
+        ));
+        assert!(!project.join("Lingo.lock").exists());
 
-        for (_, requirements, location) in merged {
-            //TODO: replace this in the future by first merging all the requirements
-            // (determine upper and lower bound)
+        resolve(
+            &project,
+            mylib.clone(),
+            &HashMap::new(),
+            FetchPolicy::default(),
+        )
+        .unwrap();
+        let lock_file = fs::read_to_string(project.join("Lingo.lock")).unwrap();
+        assert!(resolve(&project, mylib, &HashMap::new(), locked).is_ok());
 
-            let mut filtered_results: Vec<&DependencyTreeNode> = location
-                .into_iter()
-                .filter(|location| {
-                    let filter = |version: &Versioning| {
-                        for requirement in &requirements {
-                            if !requirement.matches(version) {
-                                return false;
-                            }
-                        }
-                        true
-                    };
+        // a new dependency would change the lock file
+        let changed = dependencies(&format!(
+            "mylib = {{ version = \"*\", path = \"{}\" }}\nother = {{ version = \"*\", path = \"{}\" }}\n",
+            dir.path().join("mylib").display(),
+            dir.path().join("other").display()
+        ));
+        assert!(is_error(
+            resolve(&project, changed, &HashMap::new(), locked),
+            LingoError::LockFileOutdated
+        ));
+        assert_eq!(
+            fs::read_to_string(project.join("Lingo.lock")).unwrap(),
+            lock_file
+        );
+    }
 
-                    filter(&location.version)
-                })
-                .collect();
+    #[test]
+    fn offline_does_not_fetch_from_the_network() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let offline = FetchPolicy {
+            offline: true,
+            ..Default::default()
+        };
 
-            if filtered_results.is_empty() {
-                error!("no viable package was found that fulfills all the requirements");
-            }
+        assert!(is_error(
+            resolve(
+                dir.path(),
+                dependencies(
+                    "mylib = { version = \"*\", tarball = \"https://example.com/mylib.tar.gz\" }\n"
+                ),
+                &HashMap::new(),
+                offline
+            ),
+            LingoError::OfflineFetch("https://example.com/mylib.tar.gz".to_string())
+        ));
+        assert!(is_error(
+            resolve(
+                dir.path(),
+                dependencies(
+                    "mylib = { version = \"*\", git = \"https://example.com/mylib.git\" }\n"
+                ),
+                &HashMap::new(),
+                offline
+            ),
+            LingoError::OfflineFetch("https://example.com/mylib.git".to_string())
+        ));
+    }
+
+    #[test]
+    fn migration_computes_checksums() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let mylib = dir.path().join("mylib");
+        let sub = dir.path().join("sub");
+        library(
+            &mylib,
+            "mylib",
+            "0.1.0",
+            &format!(
+                "sub = {{ version = \"*\", path = \"{}\" }}\n",
+                sub.display()
+            ),
+        );
+        library(&sub, "sub", "0.2.0", "");
+        fs::create_dir_all(&project).unwrap();
+
+        // written by lingo before the format was versioned, the checksums were computed over
+        // the working directory and are useless
+        fs::write(
+            project.join("Lingo.lock"),
+            format!(
+                "[mylib]\nname = \"mylib\"\nversion = \"0.1.0\"\nsource = \"path+{}\"\nchecksum = \"666d887870a8c6c2631081be649f2e28664decac\"\ndependencies = [\"sub\"]\n\n[sub]\nname = \"sub\"\nversion = \"0.2.0\"\nsource = \"path+{}\"\nchecksum = \"3b85ec76be5f490defea060a50229a2d9d2b2bb0\"\n",
+                mylib.display(),
+                sub.display()
+            ),
+        )
+        .unwrap();
+
+        // the migration does not change the resolution, hence it is done with --locked as well
+        let locked = FetchPolicy {
+            locked: true,
+            ..Default::default()
+        };
+        let direct = dependencies(&format!(
+            "mylib = {{ version = \"*\", path = \"{}\" }}\n",
+            mylib.display()
+        ));
+        resolve(&project, direct, &HashMap::new(), locked).unwrap();
+
+        let lock = DependencyLock::read(&project.join("Lingo.lock")).unwrap();
+        assert!(!lock.migrated());
+        assert_eq!(
+            lock.dependencies["mylib"].checksum,
+            checksum::checksum_dir(&mylib).unwrap()
+        );
+        assert_eq!(
+            lock.dependencies["sub"].checksum,
+            checksum::checksum_dir(&sub).unwrap()
+        );
+        assert_eq!(lock.dependencies["mylib"].dependencies[0].name, "sub");
+    }
+
+    #[test]
+    fn patches_transitive_dependencies() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let mylib = dir.path().join("mylib");
+        let sub = dir.path().join("sub");
+        let fork = dir.path().join("fork");
+        library(
+            &mylib,
+            "mylib",
+            "0.1.0",
+            &format!(
+                "sub = {{ version = \"^0.1.0\", path = \"{}\" }}\n",
+                sub.display()
+            ),
+        );
+        library(&sub, "sub", "0.1.0", "");
+        library(&fork, "sub", "0.1.1", "");
+        fs::create_dir_all(&project).unwrap();
 
-            filtered_results.sort_by_key(|value| value.version.clone());
+        let patches = toml::from_str::<HashMap<String, PatchDetails>>(&format!(
+            "sub = {{ path = \"{}\" }}\n",
+            fork.display()
+        ))
+        .unwrap();
+        resolve(
+            &project,
+            dependencies(&format!(
+                "mylib = {{ version = \"*\", path = \"{}\" }}\n",
+                mylib.display()
+            )),
+            &patches,
+            FetchPolicy::default(),
+        )
+        .unwrap();
 
-            let package = filtered_results
-                .last()
-                .expect("There should be at least one viable package remaining!");
+        let lock = DependencyLock::read(&project.join("Lingo.lock")).unwrap();
+        let sub = &lock.dependencies["sub"];
+        assert!(sub.patched);
+        assert_eq!(sub.source.uri, fork.display().to_string());
+        assert_eq!(sub.version.to_string(), "0.1.1");
+        assert!(!lock.dependencies["mylib"].patched);
+    }
 
-            selection.push((*package).clone());
+    /// resolves the dependency on the fake git repository and returns the locked revision
+    fn locked_tag(dir: &Path, requirement: &str) -> anyhow::Result<String> {
+        let project = dir.join("project");
+        fs::create_dir_all(&project)?;
+        let url = Url::from_directory_path(dir.join("mylib")).unwrap();
+        resolve(
+            &project,
+            dependencies(&format!(
+                "mylib = {{ version = \"{requirement}\", git = \"{url}\" }}\n"
+            )),
+            &HashMap::new(),
+            FetchPolicy::default(),
+        )?;
+        let lock = DependencyLock::read(&project.join("Lingo.lock"))?;
+        Ok(lock.dependencies["mylib"].source.rev.clone().unwrap())
+    }
+
+    #[test]
+    fn selects_the_newest_matching_tag() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let repository = dir.path().join("mylib");
+        for (tag, version) in [
+            ("v1.0.0", "1.0.0"),
+            ("v1.2.0", "1.2.0"),
+            ("1.3.0", "1.3.0"),
+            ("v2.0.0", "2.0.0"),
+            ("nightly", "2.1.0"),
+        ] {
+            library(&repository.join(tag), "mylib", version, "");
         }
 
-        Ok(selection)
+        assert_eq!(locked_tag(dir.path(), "^1.0.0").unwrap(), "1.3.0");
     }
 
-    pub fn get_target_properties(&self) -> anyhow::Result<LibraryTargetProperties> {
-        self.lock.aggregate_target_properties()
+    #[test]
+    fn uses_the_default_branch_without_tags() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        library(&dir.path().join("mylib/HEAD"), "mylib", "0.3.0", "");
+
+        assert_eq!(locked_tag(dir.path(), "^0.3.0").unwrap(), "HEAD");
+    }
+
+    #[test]
+    fn skips_tags_that_declare_another_version() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let repository = dir.path().join("mylib");
+        library(&repository.join("v1.0.0"), "mylib", "1.0.0", "");
+        // tagged before the version in Lingo.toml was bumped
+        library(&repository.join("v1.1.0"), "mylib", "1.0.9", "");
+
+        assert_eq!(locked_tag(dir.path(), "^1.0.0").unwrap(), "v1.0.0");
+    }
+
+    #[test]
+    fn includes_the_location_of_libraries() {
+        isolate_cache();
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let mqtt = dir.path().join("mqtt");
+        fs::create_dir_all(mqtt.join("src/lib")).unwrap();
+        fs::write(
+            mqtt.join("Lingo.toml"),
+            "[package]\nname = \"mqtt\"\nversion = \"0.1.0\"\n\n[lib]\nname = \"mqtt\"\nlocation = \"./src/lib\"\ntarget = \"C\"\n\n[lib.properties]\n",
+        )
+        .unwrap();
+        fs::write(mqtt.join("src/lib/Client.lf"), "reactor Client {}\n").unwrap();
+        fs::create_dir_all(&project).unwrap();
+        let direct = dependencies(&format!(
+            "mqtt = {{ version = \"*\", path = \"{}\" }}\n",
+            mqtt.display()
+        ));
+
+        // once when resolving and once when loading the packages from Lingo.lock
+        for _ in 0..2 {
+            resolve(
+                &project,
+                direct.clone(),
+                &HashMap::new(),
+                FetchPolicy::default(),
+            )
+            .unwrap();
+
+            // `import Client from <mqtt/Client.lf>` is looked up here by lfc
+            let include = project.join("build").join(INCLUDE_DIRECTORY).join("mqtt");
+            assert!(include.join("Client.lf").is_file());
+            assert!(!include.join("Lingo.toml").exists());
+        }
     }
 }
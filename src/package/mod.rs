@@ -1,5 +1,12 @@
+pub mod cache;
+pub mod editor;
+pub mod features;
+pub mod graph;
 pub mod lock;
 pub mod management;
+pub mod registry;
+pub mod sbom;
+pub mod solver;
 pub mod tree;
 
 pub mod target_properties;
@@ -7,7 +14,7 @@ pub mod target_properties;
 use serde::de::{Error, Visitor};
 use serde::{Deserializer, Serializer};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use tempfile::tempdir;
 use versions::Versioning;
 
@@ -25,23 +32,27 @@ use crate::args::{
 };
 use crate::package::tree::GitLock;
 use crate::package::{
+    features::{EnabledFeatures, Feature, FeatureRequest},
     target_properties::{
         AppTargetProperties, AppTargetPropertiesFile, LibraryTargetProperties,
         LibraryTargetPropertiesFile,
     },
-    tree::PackageDetails,
+    tree::{PackageDetails, PatchDetails},
 };
 use crate::util::{
     analyzer, copy_recursively,
     errors::{BuildResult, LingoError},
 };
-use crate::{FsReadCapability, GitCloneAndCheckoutCap, GitUrl, WhichCapability};
+use crate::{FsReadCapability, GitCloneAndCheckoutCap, GitCloneOptions, GitUrl, WhichCapability};
 
 /// place where are the build artifacts will be dropped
 pub const OUTPUT_DIRECTORY: &str = "build";
 /// name of the folder inside the `OUTPUT_DIRECTORY` where libraries
 /// will be loaded (cloned, extracted, copied) into for further processing.
 pub const LIBRARY_DIRECTORY: &str = "libraries";
+/// name of the folder inside the `OUTPUT_DIRECTORY` where the selected libraries
+/// are placed so lfc can find them.
+pub const INCLUDE_DIRECTORY: &str = "lfc_include";
 
 /// default folder for lf executable files
 const DEFAULT_EXECUTABLE_FOLDER: &str = "src";
@@ -49,6 +60,9 @@ const DEFAULT_EXECUTABLE_FOLDER: &str = "src";
 /// default folder for lf library files
 const DEFAULT_LIBRARY_FOLDER: &str = "src/lib";
 
+/// folder that is searched for test reactors if the package has no `[[test]]` entries
+const DEFAULT_TEST_FOLDER: &str = "test";
+
 fn is_valid_location_for_project(path: &std::path::Path) -> bool {
     !path.join(DEFAULT_EXECUTABLE_FOLDER).exists()
         && !path.join(".git").exists()
@@ -71,28 +85,129 @@ pub struct ConfigFile {
     #[serde(rename = "app")]
     pub apps: Option<Vec<AppFile>>,
 
+    /// test apps, they are only built by `lingo test`
+    #[serde(rename = "test", default, skip_serializing_if = "Option::is_none")]
+    pub tests: Option<Vec<AppFile>>,
+
     /// library exported by this Lingo Toml
     #[serde(rename = "lib")]
     pub library: Option<LibraryFile>,
 
     /// Dependencies for required to build this Lingua-Franca Project
+    #[serde(default)]
     pub dependencies: HashMap<String, PackageDetails>,
+
+    /// Dependencies that are only required to build the tests
+    #[serde(
+        rename = "dev-dependencies",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub dev_dependencies: HashMap<String, PackageDetails>,
+
+    /// Replaces the source of direct or transitive dependencies with the given name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub patch: HashMap<String, PatchDetails>,
+
+    /// Optional parts of this package
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, Feature>,
 }
 
 /// This struct is used after filling in all the defaults
 #[derive(Clone)]
 pub struct Config {
+    /// Absolute path to the directory where the Lingo.toml file is located.
+    pub root_path: PathBuf,
+
     /// top level package description
     pub package: PackageDescription,
 
     /// list of apps defined inside this package
     pub apps: Vec<App>,
 
+    /// test apps defined inside this package
+    pub tests: Vec<App>,
+
     /// library exported by this package
     pub library: Option<Library>,
 
     /// Dependencies for required to build this Lingua-Franca Project
     pub dependencies: HashMap<String, PackageDetails>,
+
+    /// Dependencies that are only required to build the tests
+    pub dev_dependencies: HashMap<String, PackageDetails>,
+
+    /// Replaced sources of direct or transitive dependencies
+    pub patches: HashMap<String, PatchDetails>,
+
+    /// Optional parts of this package
+    pub features: BTreeMap<String, Feature>,
+}
+
+impl Config {
+    /// dependencies of the package without the optional dependencies that are not enabled by
+    /// its default features
+    pub fn enabled_dependencies(&self) -> anyhow::Result<Vec<(String, PackageDetails)>> {
+        let enabled = EnabledFeatures::enable(
+            &self.package.name,
+            &self.features,
+            &FeatureRequest::default(),
+        )?;
+        let mut dependencies = Vec::from_iter(self.dependencies.clone());
+        dependencies.sort_by(|(a, _), (b, _)| a.cmp(b));
+        enabled.apply(&self.package.name, &dependencies)
+    }
+
+    /// dependencies recorded in the Lingo.lock, the dev-dependencies are locked as well so that
+    /// building and testing the package use the same lock file
+    pub fn locked_dependencies(&self) -> anyhow::Result<Vec<(String, PackageDetails)>> {
+        let mut test_build = self.clone();
+        test_build.test_build(false);
+        test_build.enabled_dependencies()
+    }
+
+    /// Packages without `[[test]]` entries use every main reactor inside the test folder as a
+    /// test.
+    pub fn discover_tests(&mut self) -> io::Result<()> {
+        let test_folder = self.root_path.join(DEFAULT_TEST_FOLDER);
+        if !self.tests.is_empty() || !test_folder.is_dir() {
+            return Ok(());
+        }
+
+        let mut main_reactors = analyzer::find_main_reactors(&test_folder)?;
+        main_reactors.sort_by(|a, b| a.path.cmp(&b.path));
+        self.tests = main_reactors
+            .into_iter()
+            .map(|spec| {
+                AppFile {
+                    name: Some(spec.name),
+                    main: Some(spec.path),
+                    target: spec.target,
+                    platform: None,
+                    properties: Default::default(),
+                    expect: None,
+                    tags: vec![],
+                }
+                .convert(&self.package.name, &self.root_path)
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Turns the package into the one built by `lingo test`, the tests replace the apps unless
+    /// `include_apps` is set and the dev-dependencies are added to the dependencies.
+    pub fn test_build(&mut self, include_apps: bool) {
+        let tests = std::mem::take(&mut self.tests);
+        if include_apps {
+            self.apps.extend(tests);
+        } else {
+            self.apps = tests;
+        }
+        for (name, details) in std::mem::take(&mut self.dev_dependencies) {
+            self.dependencies.entry(name).or_insert(details);
+        }
+    }
 }
 
 /// The Format inside the Lingo.toml under [lib]
@@ -104,16 +219,33 @@ pub struct LibraryFile {
     /// if not specified will default to ./lib
     pub location: Option<PathBuf>,
 
-    /// target language of the library
-    pub target: TargetLanguage,
+    /// target languages the library can be used from, either one or a list
+    pub target: OneOrMany<TargetLanguage>,
 
-    /// platform of this project
-    pub platform: Option<Platform>,
+    /// platforms the library can be used on, if not specified it works on every platform
+    pub platform: Option<OneOrMany<Platform>>,
 
     /// target properties of that lingua-franca app
     pub properties: LibraryTargetPropertiesFile,
 }
 
+/// a value in the Lingo.toml that can either be given once or as a list
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T: Clone> OneOrMany<T> {
+    pub fn to_vec(&self) -> Vec<T> {
+        match self {
+            OneOrMany::One(value) => vec![value.clone()],
+            OneOrMany::Many(values) => values.clone(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Library {
     /// if not specified will default to value specified in the package description
@@ -122,11 +254,11 @@ pub struct Library {
     /// if not specified will default to ./src
     pub location: PathBuf,
 
-    /// target of the app
-    pub target: TargetLanguage,
+    /// target languages the library can be used from
+    pub targets: Vec<TargetLanguage>,
 
-    /// platform of this project
-    pub platform: Platform,
+    /// platforms the library can be used on, empty if it works on every platform
+    pub platforms: Vec<Platform>,
 
     /// target properties of that lingua-franca app
     pub properties: LibraryTargetProperties,
@@ -152,6 +284,52 @@ pub struct AppFile {
 
     /// target properties of that lingua-franca app
     pub properties: AppTargetPropertiesFile,
+
+    /// output the app has to print when it runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect: Option<ExpectedOutput>,
+
+    /// free-form tags to select apps on the command line
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Output an app is expected to print, checked by `lingo run` and `lingo test`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ExpectedOutput {
+    /// file containing the exact standard output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<PathBuf>,
+
+    /// file containing the exact standard error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<PathBuf>,
+
+    /// regexes that have to match a part of the standard output
+    #[serde(
+        rename = "stdout-regex",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub stdout_regex: Vec<String>,
+
+    /// regexes that have to match a part of the standard error
+    #[serde(
+        rename = "stderr-regex",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub stderr_regex: Vec<String>,
+}
+
+impl ExpectedOutput {
+    fn from(self, path: &Path) -> Self {
+        ExpectedOutput {
+            stdout: self.stdout.map(|stdout| path.join(stdout)),
+            stderr: self.stderr.map(|stderr| path.join(stderr)),
+            ..self
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -172,6 +350,10 @@ pub struct App {
     pub platform: Platform,
     /// target properties of that lingua-franca app
     pub properties: AppTargetProperties,
+    /// output the app has to print when it runs, the files are absolute paths
+    pub expect: Option<ExpectedOutput>,
+    /// free-form tags to select apps on the command line
+    pub tags: Vec<String>,
 }
 
 impl AppFile {
@@ -213,6 +395,8 @@ impl AppFile {
             target: self.target,
             platform: self.platform.unwrap_or(Platform::Native),
             properties: self.properties.from(path),
+            expect: self.expect.map(|expect| expect.from(path)),
+            tags: self.tags,
         }
     }
 }
@@ -238,8 +422,11 @@ impl LibraryFile {
                 abs.push(self.location.unwrap_or(DEFAULT_LIBRARY_FOLDER.into()));
                 abs
             },
-            target: self.target,
-            platform: self.platform.unwrap_or(Platform::Native),
+            targets: self.target.to_vec(),
+            platforms: self
+                .platform
+                .map(|platform| platform.to_vec())
+                .unwrap_or_default(),
             properties: self.properties.from(path),
             output_root: path.join(OUTPUT_DIRECTORY),
         }
@@ -349,6 +536,8 @@ impl ConfigFile {
                 target: spec.target,
                 platform: Some(init_args.platform),
                 properties: Default::default(),
+                expect: None,
+                tags: vec![],
             })
             .collect::<Vec<_>>();
 
@@ -368,7 +557,11 @@ impl ConfigFile {
                 description: None,
             },
             dependencies: HashMap::default(),
+            dev_dependencies: HashMap::default(),
+            patch: HashMap::default(),
+            features: BTreeMap::default(),
             apps: Some(app_specs),
+            tests: None,
             library: Option::default(),
         };
         Ok(result)
@@ -421,7 +614,12 @@ impl ConfigFile {
             None
         };
 
-        clone(GitUrl::from(url), tmp_path, git_rev)?;
+        clone(
+            GitUrl::from(url),
+            tmp_path,
+            git_rev,
+            GitCloneOptions::default(),
+        )?;
 
         // Copy the cloned template repo into the project directory
         copy_recursively(tmp_path, Path::new("."))?;
@@ -495,6 +693,7 @@ impl ConfigFile {
         let package_name = &self.package.name;
 
         Config {
+            root_path: path.to_path_buf(),
             //properties: self.properties,
             apps: self
                 .apps
@@ -502,9 +701,18 @@ impl ConfigFile {
                 .into_iter()
                 .map(|app_file| app_file.convert(package_name, path))
                 .collect(),
+            tests: self
+                .tests
+                .unwrap_or_default()
+                .into_iter()
+                .map(|app_file| app_file.convert(package_name, path))
+                .collect(),
             package: self.package.clone(),
             library: self.library.map(|lib| lib.convert(package_name, path)),
             dependencies: self.dependencies,
+            dev_dependencies: self.dev_dependencies,
+            patches: self.patch,
+            features: self.features,
         }
     }
 }
@@ -0,0 +1,118 @@
+use serde_derive::{Deserialize, Serialize};
+use tempfile::TempDir;
+use url::Url;
+use versions::Versioning;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::package::{deserialize_version, serialize_version};
+use crate::util::errors::LingoError;
+use crate::{GitCloneAndCheckoutCap, GitCloneOptions, GitUrl};
+
+/// environment variable that configures the location of the registry index
+pub const REGISTRY_ENV_VARIABLE: &str = "LINGO_REGISTRY";
+
+/// A single published version of a package inside the registry index
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RegistryEntry {
+    #[serde(
+        serialize_with = "serialize_version",
+        deserialize_with = "deserialize_version"
+    )]
+    pub version: Versioning,
+    /// url of the archive, relative paths are resolved against the index directory
+    pub tarball: String,
+    /// sha256 of the archive
+    pub sha256: String,
+}
+
+/// Format of the `<name>.toml` files inside the registry index
+#[derive(Deserialize, Serialize)]
+struct RegistryPackageFile {
+    #[serde(rename = "version", default)]
+    versions: Vec<RegistryEntry>,
+}
+
+/// A registry index is a directory or git repository that contains one `<name>.toml` file per
+/// package, listing all published versions of this package.
+pub struct Registry {
+    /// directory containing the index
+    index: PathBuf,
+    /// keeps the checkout of a git hosted index alive
+    _checkout: Option<TempDir>,
+}
+
+impl Registry {
+    /// The location is either a git url, a file url or a path to a local directory. In offline
+    /// mode only local indices can be opened.
+    pub fn open(
+        location: &str,
+        offline: bool,
+        clone: &GitCloneAndCheckoutCap,
+    ) -> anyhow::Result<Registry> {
+        match Url::parse(location) {
+            Ok(url) if url.scheme() == "file" => Ok(Registry {
+                index: url
+                    .to_file_path()
+                    .map_err(|_| LingoError::InvalidRegistry(location.to_string()))?,
+                _checkout: None,
+            }),
+            Ok(url) if url.scheme().len() > 1 => {
+                if offline {
+                    return Err(LingoError::OfflineFetch(location.to_string()).into());
+                }
+                let checkout = tempfile::tempdir()?;
+                // only the current state of the index is of interest
+                let options = GitCloneOptions {
+                    shallow: true,
+                    submodules: false,
+                };
+                clone(GitUrl::from(url.as_str()), checkout.path(), None, options)?;
+                Ok(Registry {
+                    index: checkout.path().to_path_buf(),
+                    _checkout: Some(checkout),
+                })
+            }
+            // everything else (including windows drive letters) is a local directory
+            _ => Ok(Registry {
+                index: PathBuf::from(location),
+                _checkout: None,
+            }),
+        }
+    }
+
+    /// location of the registry, either the one given by the dependency or the one that is
+    /// configured globally via the environment
+    pub fn location(dependency_registry: Option<&str>) -> anyhow::Result<String> {
+        match dependency_registry {
+            Some(location) => Ok(location.to_string()),
+            None => std::env::var(REGISTRY_ENV_VARIABLE)
+                .map_err(|_| LingoError::NoRegistryConfigured.into()),
+        }
+    }
+
+    /// all published versions of a package
+    pub fn versions(&self, name: &str) -> anyhow::Result<Vec<RegistryEntry>> {
+        let package_file = self.index.join(format!("{}.toml", name));
+        if !package_file.exists() {
+            return Err(LingoError::NotInRegistry(name.to_string()).into());
+        }
+
+        let file = toml::from_str::<RegistryPackageFile>(&fs::read_to_string(&package_file)?)?;
+        Ok(file.versions)
+    }
+
+    /// absolute url of the archive of the given entry
+    pub fn tarball_url(&self, entry: &RegistryEntry) -> anyhow::Result<Url> {
+        if let Ok(url) = Url::parse(&entry.tarball) {
+            if url.scheme().len() > 1 {
+                return Ok(url);
+            }
+        }
+
+        let path = fs::canonicalize(self.index.join(Path::new(&entry.tarball)))?;
+        Url::from_file_path(&path)
+            .map_err(|_| LingoError::InvalidRegistry(path.display().to_string()).into())
+    }
+}
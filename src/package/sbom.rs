@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use serde_json::{json, Value};
+use versions::Versioning;
+
+use crate::package::lock::{DependencyLock, PackageLockSource, PackageLockSourceType};
+use crate::package::{Config, ConfigFile, PackageDescription};
+use crate::util::time;
+
+/// A package that went into the build together with the metadata from its Lingo.toml
+struct Component {
+    name: String,
+    version: Versioning,
+    description: PackageDescription,
+    /// the root package has no source, it is the package that is built
+    source: Option<PackageLockSource>,
+    /// sha256 of the archive the package was downloaded as
+    sha256: Option<String>,
+    /// checksum lingo computes over the files of the package, see [`crate::util::checksum`]
+    checksum: Option<String>,
+    dependencies: Vec<String>,
+}
+
+/// Bill of materials of a package, covering every package it depends on.
+pub struct BillOfMaterials {
+    root: Component,
+    components: Vec<Component>,
+}
+
+impl BillOfMaterials {
+    /// Creates the bill of materials from the lock file. The license and authors of every
+    /// package are read from its Lingo.toml inside the lfc include folder.
+    pub fn new(config: &Config, lock: &DependencyLock) -> anyhow::Result<BillOfMaterials> {
+        let root_dependencies = config
+            .enabled_dependencies()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+
+        let mut components = Vec::new();
+        for library in lock.libraries() {
+            let name = &library.name;
+            let package_lock = &lock.dependencies[name];
+            let lingo_toml = library.location.join("Lingo.toml");
+            let config_file = toml::from_str::<ConfigFile>(&fs::read_to_string(&lingo_toml)?)?;
+            components.push(Component {
+                name: name.clone(),
+                version: package_lock.version.clone(),
+                description: config_file.package,
+                source: Some(package_lock.source.clone()),
+                sha256: package_lock.sha256.clone(),
+                checksum: Some(package_lock.checksum.clone()),
+                dependencies: package_lock
+                    .dependencies
+                    .iter()
+                    .map(|dependency| dependency.name.clone())
+                    .collect(),
+            });
+        }
+        components.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(BillOfMaterials {
+            root: Component {
+                name: config.package.name.clone(),
+                version: config.package.version.clone(),
+                description: config.package.clone(),
+                source: None,
+                sha256: None,
+                checksum: None,
+                dependencies: root_dependencies,
+            },
+            components,
+        })
+    }
+
+    /// Lists the dependencies grouped by their declared license, packages without a license
+    /// are listed last.
+    pub fn render_licenses(&self) -> String {
+        let mut groups = BTreeMap::<Option<&str>, Vec<&Component>>::new();
+        for component in &self.components {
+            groups
+                .entry(component.description.license.as_deref())
+                .or_default()
+                .push(component);
+        }
+
+        let mut output = String::new();
+        let licensed = groups.iter().filter(|(license, _)| license.is_some());
+        let unlicensed = groups.iter().filter(|(license, _)| license.is_none());
+        for (license, components) in licensed.chain(unlicensed) {
+            output += &format!(
+                "{} ({})\n",
+                license.unwrap_or("no license declared"),
+                components.len()
+            );
+            for component in components {
+                output += &format!("    {} v{}", component.name, component.version);
+                if let Some(authors) = &component.description.authors {
+                    output += &format!(" by {}", authors.join(", "));
+                }
+                output += "\n";
+            }
+        }
+        output
+    }
+
+    /// the bill of materials as CycloneDX 1.5 json
+    pub fn cyclonedx(&self) -> Value {
+        let component = |component: &Component| {
+            let mut value = json!({
+                "type": "library",
+                "bom-ref": component.name,
+                "name": component.name,
+                "version": component.version.to_string(),
+            });
+            let object = value.as_object_mut().unwrap();
+            if let Some(description) = &component.description.description {
+                object.insert("description".into(), json!(description));
+            }
+            if let Some(authors) = &component.description.authors {
+                object.insert("author".into(), json!(authors.join(", ")));
+            }
+            if let Some(license) = &component.description.license {
+                object.insert("licenses".into(), json!([{ "expression": license }]));
+            }
+            if let Some(sha256) = &component.sha256 {
+                object.insert(
+                    "hashes".into(),
+                    json!([{ "alg": "SHA-256", "content": sha256 }]),
+                );
+            }
+
+            let mut references = Vec::new();
+            let mut properties = Vec::new();
+            if let Some(source) = &component.source {
+                let kind = match source.source_type {
+                    PackageLockSourceType::GIT => "vcs",
+                    _ => "distribution",
+                };
+                references.push(json!({ "type": kind, "url": source.uri }));
+                properties.push(json!({ "name": "lingo:source", "value": source.to_string() }));
+                if let Some(rev) = &source.rev {
+                    properties.push(json!({ "name": "lingo:rev", "value": rev }));
+                }
+            }
+            // the checksum is computed over the files of the package, not a downloaded file
+            if let Some(checksum) = &component.checksum {
+                properties.push(json!({ "name": "lingo:checksum", "value": checksum }));
+            }
+            if !properties.is_empty() {
+                object.insert("properties".into(), json!(properties));
+            }
+            if let Some(website) = &component.description.website {
+                references.push(json!({ "type": "website", "url": website }));
+            }
+            if !references.is_empty() {
+                object.insert("externalReferences".into(), json!(references));
+            }
+            value
+        };
+
+        let mut root = component(&self.root);
+        root["type"] = json!("application");
+
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "timestamp": time::timestamp(time::now()),
+                "tools": [{ "name": "lingo", "version": env!("CARGO_PKG_VERSION") }],
+                "component": root,
+            },
+            "components": self.components.iter().map(component).collect::<Vec<_>>(),
+            "dependencies": self
+                .all_components()
+                .map(|component| json!({
+                    "ref": component.name,
+                    "dependsOn": component.dependencies,
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// the bill of materials as SPDX 2.3 json
+    pub fn spdx(&self) -> Value {
+        let now = time::now();
+        let package = |component: &Component| {
+            let mut value = json!({
+                "SPDXID": spdx_id(&component.name),
+                "name": component.name,
+                "versionInfo": component.version.to_string(),
+                "downloadLocation": component
+                    .source
+                    .as_ref()
+                    .map(download_location)
+                    .unwrap_or_else(|| "NOASSERTION".to_string()),
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": component
+                    .description
+                    .license
+                    .as_deref()
+                    .unwrap_or("NOASSERTION"),
+                "copyrightText": "NOASSERTION",
+                "filesAnalyzed": false,
+            });
+            let object = value.as_object_mut().unwrap();
+            if let Some(sha256) = &component.sha256 {
+                object.insert(
+                    "checksums".into(),
+                    json!([{ "algorithm": "SHA256", "checksumValue": sha256 }]),
+                );
+            }
+            // SPDX checksums describe a downloaded file, the checksum over the files of the
+            // package is recorded as an annotation
+            if let Some(checksum) = &component.checksum {
+                object.insert(
+                    "annotations".into(),
+                    json!([{
+                        "annotationDate": time::timestamp(now),
+                        "annotationType": "OTHER",
+                        "annotator": format!("Tool: lingo-{}", env!("CARGO_PKG_VERSION")),
+                        "comment": format!("lingo:checksum {checksum}"),
+                    }]),
+                );
+            }
+            if let Some(authors) = &component.description.authors {
+                object.insert(
+                    "originator".into(),
+                    json!(format!("Person: {}", authors.join(", "))),
+                );
+            }
+            if let Some(website) = &component.description.website {
+                object.insert("homepage".into(), json!(website));
+            }
+            if let Some(description) = &component.description.description {
+                object.insert("description".into(), json!(description));
+            }
+            value
+        };
+
+        let mut relationships = vec![json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": spdx_id(&self.root.name),
+        })];
+        for component in self.all_components() {
+            for dependency in &component.dependencies {
+                relationships.push(json!({
+                    "spdxElementId": spdx_id(&component.name),
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": spdx_id(dependency),
+                }));
+            }
+        }
+
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": self.root.name,
+            "documentNamespace": format!(
+                "https://spdx.org/spdxdocs/{}-{}-{}",
+                self.root.name, self.root.version, now
+            ),
+            "creationInfo": {
+                "created": time::timestamp(now),
+                "creators": [format!("Tool: lingo-{}", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": self.all_components().map(package).collect::<Vec<_>>(),
+            "relationships": relationships,
+        })
+    }
+
+    fn all_components(&self) -> impl Iterator<Item = &Component> {
+        std::iter::once(&self.root).chain(self.components.iter())
+    }
+}
+
+/// SPDX identifiers may only contain letters, numbers, `.` and `-`
+fn spdx_id(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    format!("SPDXRef-Package-{name}")
+}
+
+/// download location in the format of SPDX, e.g. `git+https://host/repo.git@rev`
+fn download_location(source: &PackageLockSource) -> String {
+    match (&source.source_type, &source.rev) {
+        (PackageLockSourceType::GIT, Some(rev)) => format!("git+{}@{}", source.uri, rev),
+        (PackageLockSourceType::GIT, None) => format!("git+{}", source.uri),
+        // local directories cannot be downloaded
+        (PackageLockSourceType::PATH, _) => "NOASSERTION".to_string(),
+        _ => source.uri.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(name: &str, source: PackageLockSourceType, sha256: Option<&str>) -> Component {
+        Component {
+            name: name.to_string(),
+            version: Versioning::new("1.0.0").unwrap(),
+            description: PackageDescription {
+                name: name.to_string(),
+                version: Versioning::new("1.0.0").unwrap(),
+                authors: None,
+                website: None,
+                license: None,
+                description: None,
+            },
+            source: Some(PackageLockSource {
+                source_type: source,
+                uri: format!("https://example.com/{name}"),
+                rev: None,
+            }),
+            sha256: sha256.map(str::to_string),
+            checksum: Some(format!("{name}-files")),
+            dependencies: vec![],
+        }
+    }
+
+    #[test]
+    fn hashes_only_archives() {
+        let mut root = component("app", PackageLockSourceType::PATH, None);
+        root.source = None;
+        root.checksum = None;
+        let bom = BillOfMaterials {
+            root,
+            components: vec![
+                component("archived", PackageLockSourceType::TARBALL, Some("abcd")),
+                component("cloned", PackageLockSourceType::GIT, None),
+            ],
+        };
+
+        let cyclonedx = bom.cyclonedx();
+        let archived = &cyclonedx["components"][0];
+        assert_eq!(
+            archived["hashes"],
+            json!([{ "alg": "SHA-256", "content": "abcd" }])
+        );
+        assert!(archived["properties"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "name": "lingo:checksum", "value": "archived-files" })));
+        assert!(cyclonedx["components"][1].get("hashes").is_none());
+
+        let spdx = bom.spdx();
+        assert_eq!(
+            spdx["packages"][1]["checksums"],
+            json!([{ "algorithm": "SHA256", "checksumValue": "abcd" }])
+        );
+        assert!(spdx["packages"][2].get("checksums").is_none());
+        assert_eq!(
+            spdx["packages"][2]["annotations"][0]["comment"],
+            "lingo:checksum cloned-files"
+        );
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+use versions::Versioning;
+
+use crate::package::tree::PackageDetails;
+
+/// A version of a package that can be selected by the solver
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    /// version of the package
+    pub version: Versioning,
+    /// where to obtain exactly this version from
+    pub package: PackageDetails,
+}
+
+/// Gives the solver access to the available versions of a package and their dependencies.
+pub trait PackageProvider {
+    /// all versions of the package that can be obtained from the source described by `package`
+    fn candidates(
+        &mut self,
+        name: &str,
+        package: &PackageDetails,
+    ) -> anyhow::Result<Vec<Candidate>>;
+
+    /// dependencies of the given version of the package, `None` if the candidate turns out not
+    /// to provide its version, e.g. a tag whose Lingo.toml declares another version
+    fn dependencies(
+        &mut self,
+        name: &str,
+        candidate: &Candidate,
+    ) -> anyhow::Result<Option<Vec<(String, PackageDetails)>>>;
+}
+
+/// A requirement on a package together with the package that imposed it
+#[derive(Clone, Debug)]
+pub struct Incompatibility {
+    /// package and version that requires the package
+    pub dependant: String,
+    /// name of the package that requires the package, `None` for the root package
+    pub dependant_name: Option<String>,
+    /// requirement and source of the required package
+    pub package: PackageDetails,
+}
+
+/// Explanation why no set of versions exists that satisfies all requirements
+#[derive(Debug)]
+pub struct Conflict {
+    /// package for which no version could be selected
+    pub name: String,
+    /// all requirements that were imposed on the package at this point
+    pub requirements: Vec<Incompatibility>,
+    /// all versions that were available for this package
+    pub available: Vec<Versioning>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "cannot find a version of {} that satisfies all requirements:",
+            self.name
+        )?;
+        for requirement in &self.requirements {
+            writeln!(
+                f,
+                "    {} requires {} {}",
+                requirement.dependant, self.name, requirement.package.version
+            )?;
+        }
+
+        if self.available.is_empty() {
+            write!(f, "no versions of {} are available", self.name)
+        } else {
+            write!(
+                f,
+                "available versions of {}: {}",
+                self.name,
+                self.available
+                    .iter()
+                    .map(|version| version.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }
+    }
+}
+
+/// Result of the solver, the selected version of every package and its dependencies
+#[derive(Default, Debug)]
+pub struct Solution {
+    /// selected version for every package
+    pub selected: BTreeMap<String, Candidate>,
+    /// dependencies of every selected package
+    pub dependencies: BTreeMap<String, Vec<(String, PackageDetails)>>,
+    /// requirements imposed on every selected package
+    pub requirements: BTreeMap<String, Vec<Incompatibility>>,
+}
+
+/// Solver that selects exactly one version per package, such that all requirements that the
+/// selected packages impose on each other are satisfied. Newer versions are preferred over
+/// older ones.
+///
+/// Every failure is traced back to the selected packages that caused it. These packages are
+/// learned as an incompatibility that is never selected again, and the search jumps back to
+/// the most recent of them instead of trying other versions of packages that were not
+/// involved.
+pub struct Solver<'a, P: PackageProvider> {
+    provider: &'a mut P,
+    /// requirements imposed on every package by the currently selected packages
+    requirements: BTreeMap<String, Vec<Incompatibility>>,
+    /// partial solution
+    solution: Solution,
+    /// combinations of selected versions that cannot be part of any solution
+    learned: Vec<BTreeMap<String, SelectedVersion>>,
+    /// the conflict that caused the failure that is jumped back from
+    conflict: Option<Conflict>,
+}
+
+/// version of a package together with the source it was selected from
+type SelectedVersion = (Versioning, String);
+
+fn selected_version(candidate: &Candidate) -> SelectedVersion {
+    (candidate.version.clone(), candidate.package.source_id())
+}
+
+impl<'a, P: PackageProvider> Solver<'a, P> {
+    pub fn new(provider: &'a mut P) -> Self {
+        Self {
+            provider,
+            requirements: BTreeMap::new(),
+            solution: Solution::default(),
+            learned: vec![],
+            conflict: None,
+        }
+    }
+
+    /// Solves the dependencies of the root package. Errors of the provider are passed on,
+    /// if the requirements cannot be satisfied the conflict is returned.
+    pub fn solve(
+        mut self,
+        root: &str,
+        dependencies: Vec<(String, PackageDetails)>,
+    ) -> anyhow::Result<Result<Solution, Conflict>> {
+        self.add_requirements(root, None, &dependencies);
+
+        match self.search()? {
+            Ok(()) => {
+                self.solution.requirements = self.requirements;
+                Ok(Ok(self.solution))
+            }
+            Err(_) => Ok(Err(self.conflict.unwrap_or_else(|| Conflict {
+                name: root.to_string(),
+                requirements: vec![],
+                available: vec![],
+            }))),
+        }
+    }
+
+    fn add_requirements(
+        &mut self,
+        dependant: &str,
+        dependant_name: Option<&str>,
+        dependencies: &[(String, PackageDetails)],
+    ) {
+        for (name, package) in dependencies {
+            self.requirements
+                .entry(name.clone())
+                .or_default()
+                .push(Incompatibility {
+                    dependant: dependant.to_string(),
+                    dependant_name: dependant_name.map(str::to_string),
+                    package: package.clone(),
+                });
+        }
+    }
+
+    fn remove_requirements(&mut self, dependencies: &[(String, PackageDetails)]) {
+        // requirements are only ever appended, hence the last ones are the ones to remove
+        for (name, _) in dependencies.iter().rev() {
+            if let Some(requirements) = self.requirements.get_mut(name) {
+                requirements.pop();
+                if requirements.is_empty() {
+                    self.requirements.remove(name);
+                }
+            }
+        }
+    }
+
+    /// remembers that the currently selected versions of these packages cannot be combined
+    fn learn(&mut self, culprits: &BTreeSet<String>) {
+        if !culprits.is_empty() {
+            let incompatibility = culprits
+                .iter()
+                .map(|name| {
+                    (
+                        name.clone(),
+                        selected_version(&self.solution.selected[name]),
+                    )
+                })
+                .collect();
+            self.learned.push(incompatibility);
+        }
+    }
+
+    /// the other packages of a learned incompatibility that the candidate completes
+    fn learned_culprits(&self, name: &str, candidate: &Candidate) -> Option<BTreeSet<String>> {
+        let version = selected_version(candidate);
+        self.learned
+            .iter()
+            .find(|incompatibility| {
+                incompatibility.get(name) == Some(&version)
+                    && incompatibility.iter().all(|(other, other_version)| {
+                        other == name
+                            || self.solution.selected.get(other).is_some_and(|selected| {
+                                selected_version(selected) == *other_version
+                            })
+                    })
+            })
+            .map(|incompatibility| {
+                incompatibility
+                    .keys()
+                    .filter(|other| *other != name)
+                    .cloned()
+                    .collect()
+            })
+    }
+
+    /// Selects a version for every required package. On failure, the selected packages that
+    /// caused it are returned.
+    fn search(&mut self) -> anyhow::Result<Result<(), BTreeSet<String>>> {
+        // the next package that is required but has no version selected yet
+        let name = match self
+            .requirements
+            .keys()
+            .find(|name| !self.solution.selected.contains_key(*name))
+        {
+            Some(name) => name.clone(),
+            None => return Ok(Ok(())),
+        };
+        let requirements = self.requirements[&name].clone();
+
+        // the dependants decide which versions of the package are acceptable, other versions
+        // of them may require different versions or not require the package at all
+        let mut culprits = requirements
+            .iter()
+            .filter_map(|requirement| requirement.dependant_name.clone())
+            .collect::<BTreeSet<_>>();
+
+        // collect the versions from all the sources the dependants asked for
+        let mut candidates = Vec::<Candidate>::new();
+        let mut sources = BTreeSet::new();
+        for requirement in &requirements {
+            if sources.insert(requirement.package.source_id()) {
+                for candidate in self.provider.candidates(&name, &requirement.package)? {
+                    if !candidates.iter().any(|existing| {
+                        existing.version == candidate.version
+                            && existing.package.source_id() == candidate.package.source_id()
+                    }) {
+                        candidates.push(candidate);
+                    }
+                }
+            }
+        }
+        let mut available = candidates
+            .iter()
+            .map(|candidate| candidate.version.clone())
+            .collect::<Vec<_>>();
+        available.sort();
+
+        candidates.retain(|candidate| {
+            requirements
+                .iter()
+                .all(|requirement| requirement.package.version.matches(&candidate.version))
+        });
+        candidates.sort_by(|a, b| b.version.cmp(&a.version));
+
+        if candidates.is_empty() {
+            self.conflict = Some(Conflict {
+                name,
+                requirements,
+                available,
+            });
+            self.learn(&culprits);
+            return Ok(Err(culprits));
+        }
+
+        for candidate in candidates {
+            // the candidate together with the selected packages already failed before
+            if let Some(learned) = self.learned_culprits(&name, &candidate) {
+                culprits.extend(learned);
+                continue;
+            }
+
+            // an invalid candidate is skipped as if it had never been available
+            let Some(dependencies) = self.provider.dependencies(&name, &candidate)? else {
+                available.retain(|version| *version != candidate.version);
+                self.conflict = Some(Conflict {
+                    name: name.clone(),
+                    requirements: requirements.clone(),
+                    available: available.clone(),
+                });
+                continue;
+            };
+            let dependant = format!("{} v{}", name, candidate.version);
+
+            // the dependencies of this candidate must be satisfied by the already selected
+            // packages, otherwise this candidate cannot be part of the solution
+            if let Some((conflicting, package)) = dependencies.iter().find(|(dep, package)| {
+                self.solution
+                    .selected
+                    .get(dep)
+                    .is_some_and(|selected| !package.version.matches(&selected.version))
+            }) {
+                let mut requirements = self.requirements[conflicting].clone();
+                requirements.push(Incompatibility {
+                    dependant,
+                    dependant_name: Some(name.clone()),
+                    package: package.clone(),
+                });
+                self.conflict = Some(Conflict {
+                    name: conflicting.clone(),
+                    requirements,
+                    available: vec![self.solution.selected[conflicting].version.clone()],
+                });
+                culprits.insert(conflicting.clone());
+                continue;
+            }
+
+            self.add_requirements(&dependant, Some(&name), &dependencies);
+            self.solution.selected.insert(name.clone(), candidate);
+            self.solution
+                .dependencies
+                .insert(name.clone(), dependencies.clone());
+
+            let cause = match self.search()? {
+                Ok(()) => return Ok(Ok(())),
+                Err(cause) => cause,
+            };
+
+            self.solution.dependencies.remove(&name);
+            self.solution.selected.remove(&name);
+            self.remove_requirements(&dependencies);
+
+            // another version of this package cannot resolve a conflict it is not part of
+            if !cause.contains(&name) {
+                return Ok(Err(cause));
+            }
+            culprits.extend(cause.into_iter().filter(|culprit| *culprit != name));
+        }
+
+        // every candidate failed, the conflict recorded last explains why
+        self.learn(&culprits);
+        Ok(Err(culprits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tree::ProjectSource;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use versions::Requirement;
+
+    /// provider backed by a fixed list of packages, versions and their dependencies
+    type StaticPackage = (
+        &'static str,
+        &'static str,
+        Vec<(&'static str, &'static str)>,
+    );
+    /// the second list contains the versions that turn out to be invalid once they are fetched
+    struct StaticProvider(Vec<StaticPackage>, Vec<(&'static str, &'static str)>);
+
+    fn details(requirement: &str) -> PackageDetails {
+        PackageDetails {
+            version: Requirement::from_str(requirement).unwrap(),
+            mutual_exclusive: ProjectSource::Path(PathBuf::new()),
+            git_tag: None,
+            git_rev: None,
+            sha256: None,
+            tarball: None,
+            subdir: None,
+            shallow: None,
+            submodules: None,
+            optional: false,
+            features: vec![],
+            default_features: None,
+        }
+    }
+
+    impl PackageProvider for StaticProvider {
+        fn candidates(
+            &mut self,
+            name: &str,
+            package: &PackageDetails,
+        ) -> anyhow::Result<Vec<Candidate>> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|(package_name, _, _)| *package_name == name)
+                .map(|(_, version, _)| Candidate {
+                    version: Versioning::new(version).unwrap(),
+                    package: package.clone(),
+                })
+                .collect())
+        }
+
+        fn dependencies(
+            &mut self,
+            name: &str,
+            candidate: &Candidate,
+        ) -> anyhow::Result<Option<Vec<(String, PackageDetails)>>> {
+            if self.1.iter().any(|(package_name, version)| {
+                *package_name == name && Versioning::new(version).unwrap() == candidate.version
+            }) {
+                return Ok(None);
+            }
+            Ok(self
+                .0
+                .iter()
+                .find(|(package_name, version, _)| {
+                    *package_name == name && Versioning::new(version).unwrap() == candidate.version
+                })
+                .map(|(_, _, dependencies)| {
+                    dependencies
+                        .iter()
+                        .map(|(name, requirement)| (name.to_string(), details(requirement)))
+                        .collect()
+                }))
+        }
+    }
+
+    #[test]
+    fn backtracks_to_older_version() {
+        let mut provider = StaticProvider(
+            vec![
+                ("a", "2.0.0", vec![("c", ">=2.0.0")]),
+                ("a", "1.0.0", vec![("c", "<2.0.0")]),
+                ("b", "1.0.0", vec![("c", "<2.0.0")]),
+                ("c", "1.0.0", vec![]),
+                ("c", "2.0.0", vec![]),
+            ],
+            vec![],
+        );
+
+        let solution = Solver::new(&mut provider)
+            .solve(
+                "root",
+                vec![
+                    ("a".to_string(), details("*")),
+                    ("b".to_string(), details("*")),
+                ],
+            )
+            .unwrap()
+            .unwrap();
+
+        let selected = solution
+            .selected
+            .iter()
+            .map(|(name, candidate)| format!("{} {}", name, candidate.version))
+            .collect::<Vec<_>>();
+        assert_eq!(selected, vec!["a 1.0.0", "b 1.0.0", "c 1.0.0"]);
+    }
+
+    #[test]
+    fn explains_conflicting_requirements() {
+        let mut provider = StaticProvider(
+            vec![
+                ("a", "1.0.0", vec![("c", "<2.0.0")]),
+                ("c", "1.0.0", vec![]),
+                ("c", "2.0.0", vec![]),
+            ],
+            vec![],
+        );
+
+        let conflict = Solver::new(&mut provider)
+            .solve(
+                "root",
+                vec![
+                    ("a".to_string(), details("*")),
+                    ("c".to_string(), details(">=2.0.0")),
+                ],
+            )
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(conflict.name, "c");
+        assert_eq!(
+            conflict.to_string(),
+            "cannot find a version of c that satisfies all requirements:\n    \
+             root requires c >=2.0.0\n    \
+             a v1.0.0 requires c <2.0.0\n\
+             available versions of c: 1.0.0, 2.0.0"
+        );
+    }
+
+    #[test]
+    fn skips_invalid_candidates() {
+        let mut provider = StaticProvider(
+            vec![
+                ("a", "2.0.0", vec![]),
+                ("a", "1.0.0", vec![]),
+                ("b", "1.0.0", vec![]),
+            ],
+            vec![("a", "2.0.0"), ("b", "1.0.0")],
+        );
+
+        let solution = Solver::new(&mut provider)
+            .solve("root", vec![("a".to_string(), details("*"))])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            solution.selected["a"].version,
+            Versioning::new("1.0.0").unwrap()
+        );
+
+        let conflict = Solver::new(&mut provider)
+            .solve("root", vec![("b".to_string(), details("*"))])
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            conflict.to_string(),
+            "cannot find a version of b that satisfies all requirements:\n    \
+             root requires b *\n\
+             no versions of b are available"
+        );
+    }
+
+    #[test]
+    fn reports_the_conflict_that_caused_the_failure() {
+        let mut provider = StaticProvider(
+            vec![
+                ("a", "2.0.0", vec![]),
+                // would fail as well, but a is not involved in the conflict on z
+                ("a", "1.0.0", vec![("w", ">=5.0.0")]),
+                ("c", "1.0.0", vec![("z", ">=2.0.0")]),
+                ("w", "1.0.0", vec![]),
+                ("z", "1.0.0", vec![]),
+                ("z", "2.0.0", vec![]),
+            ],
+            vec![],
+        );
+
+        let conflict = Solver::new(&mut provider)
+            .solve(
+                "root",
+                vec![
+                    ("a".to_string(), details("*")),
+                    ("c".to_string(), details("*")),
+                    ("z".to_string(), details("<2.0.0")),
+                ],
+            )
+            .unwrap()
+            .unwrap_err();
+
+        assert_eq!(
+            conflict.to_string(),
+            "cannot find a version of z that satisfies all requirements:\n    \
+             root requires z <2.0.0\n    \
+             c v1.0.0 requires z >=2.0.0\n\
+             available versions of z: 1.0.0, 2.0.0"
+        );
+    }
+}
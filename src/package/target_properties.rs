@@ -4,6 +4,8 @@ use std::fmt::{Display, Formatter};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use crate::util::copy_recursively;
+
 pub trait CMakeLoader {
     fn read_file(&mut self, path: &str) -> anyhow::Result<AutoCmakeLoad>;
 }
@@ -45,7 +47,7 @@ impl MergeTargetProperty for AutoCmakeLoad {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct LibraryTargetPropertiesFile {
     /// cmake include only available for C and CPP
     #[serde(rename = "cmake-include", default)]
@@ -56,7 +58,7 @@ pub struct LibraryTargetPropertiesFile {
     sources: Vec<PathBuf>,
 
     /// list of files that should be made available to the user
-    #[serde(rename = "sources", default)]
+    #[serde(rename = "artifacts", default)]
     artifacts: Vec<PathBuf>,
 }
 
@@ -65,11 +67,47 @@ pub struct LibraryTargetProperties {
     /// cmake include only available for C and CPP
     pub cmake_include: AutoCmakeLoad,
 
-    /// files that should be compiled and linked
+    /// files that should be compiled and linked, relative to the root of the library
     pub sources: Vec<PathBuf>,
 
-    /// list of files that should be made available to the user
+    /// list of files that should be made available to the user, relative to the root of the
+    /// library
     pub artifacts: Vec<PathBuf>,
+
+    /// libraries whose sources have been compiled already
+    pub precompiled: Vec<PrecompiledLibrary>,
+}
+
+/// a library that was compiled into a static library together with a CMake package config
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrecompiledLibrary {
+    /// name of the library, the imported CMake target is called `lingo::<name>`
+    pub name: String,
+    /// directory containing the builds of the library for the different variants of apps
+    pub directory: PathBuf,
+    /// native sources of the library
+    pub sources: Vec<PathBuf>,
+    /// content of the cmake-include of the library
+    pub cmake_include: String,
+}
+
+impl PrecompiledLibrary {
+    /// directory containing the `<name>Config.cmake` of the build for an app variant
+    pub fn config_directory(&self, variant: &str) -> PathBuf {
+        self.directory.join(variant)
+    }
+}
+
+impl LibraryTargetProperties {
+    /// the same properties with the sources and artifacts of a library located at `root`
+    pub fn resolve_paths(&self, root: &Path) -> LibraryTargetProperties {
+        LibraryTargetProperties {
+            cmake_include: self.cmake_include.clone(),
+            sources: self.sources.iter().map(|path| root.join(path)).collect(),
+            artifacts: self.artifacts.iter().map(|path| root.join(path)).collect(),
+            precompiled: self.precompiled.clone(),
+        }
+    }
 }
 
 impl LibraryTargetPropertiesFile {
@@ -86,6 +124,7 @@ impl LibraryTargetPropertiesFile {
             ),
             sources: self.sources,
             artifacts: self.artifacts,
+            precompiled: vec![],
         }
     }
 }
@@ -106,6 +145,15 @@ pub struct AppTargetProperties {
     /// cmake include only available for C and CPP
     cmake_include: AutoCmakeLoad,
 
+    /// files of the libraries that are compiled and linked into the app
+    sources: Vec<PathBuf>,
+
+    /// files of the libraries that are copied next to the executable
+    artifacts: Vec<PathBuf>,
+
+    /// compiled libraries that are linked into the app
+    precompiled: Vec<PrecompiledLibrary>,
+
     /// if the runtime should wait for physical time to catch up
     pub fast: bool,
 }
@@ -122,6 +170,9 @@ impl AppTargetPropertiesFile {
                     })
                     .unwrap_or_default(),
             ),
+            sources: vec![],
+            artifacts: vec![],
+            precompiled: vec![],
             fast: self.fast,
         }
     }
@@ -138,6 +189,9 @@ pub trait MergeTargetProperties {
 impl MergeTargetProperties for LibraryTargetProperties {
     fn merge(&mut self, partent: &LibraryTargetProperties) -> anyhow::Result<()> {
         self.cmake_include.merge(&partent.cmake_include)?;
+        self.sources.extend(partent.sources.iter().cloned());
+        self.artifacts.extend(partent.artifacts.iter().cloned());
+        self.precompiled.extend(partent.precompiled.iter().cloned());
         Ok(())
     }
 }
@@ -145,6 +199,9 @@ impl MergeTargetProperties for LibraryTargetProperties {
 impl MergeTargetProperties for AppTargetProperties {
     fn merge(&mut self, parent: &LibraryTargetProperties) -> anyhow::Result<()> {
         self.cmake_include.merge(&parent.cmake_include)?;
+        self.sources.extend(parent.sources.iter().cloned());
+        self.artifacts.extend(parent.artifacts.iter().cloned());
+        self.precompiled.extend(parent.precompiled.iter().cloned());
         Ok(())
     }
 }
@@ -159,13 +216,113 @@ impl MergeTargetProperties for GenericTargetProperties {
 }
 
 impl AppTargetProperties {
-    pub fn write_artifacts(&self, library_folder: &Path) -> anyhow::Result<()> {
+    /// Writes the aggregated CMake include of the app, `variant` selects the builds of the
+    /// precompiled libraries.
+    pub fn write_artifacts(&self, library_folder: &Path, variant: &str) -> anyhow::Result<()> {
         let file = library_folder.join("aggregated_cmake_include.cmake");
 
         let mut fd = std::fs::File::create(file)?;
         fd.write_all(self.cmake_include.0.as_ref())?;
+        fd.write_all(self.cmake_sources().as_ref())?;
+        fd.write_all(self.cmake_precompiled(variant).as_ref())?;
         fd.flush()?;
 
         Ok(())
     }
+
+    /// adds the native sources of the libraries to the target of the app, headers are found
+    /// next to the sources
+    fn cmake_sources(&self) -> String {
+        if self.sources.is_empty() {
+            return String::new();
+        }
+
+        let mut directories = self
+            .sources
+            .iter()
+            .filter_map(|source| source.parent())
+            .collect::<Vec<_>>();
+        directories.sort();
+        directories.dedup();
+
+        let quote = |path: &Path| format!("\n    \"{}\"", path.display());
+        format!(
+            "\n# sources of the libraries\ntarget_sources(${{LF_MAIN_TARGET}} PRIVATE{}\n)\ntarget_include_directories(${{LF_MAIN_TARGET}} PRIVATE{}\n)\n",
+            self.sources.iter().map(|source| quote(source)).collect::<String>(),
+            directories.into_iter().map(quote).collect::<String>()
+        )
+    }
+
+    /// libraries that have to be built before the app can link against them
+    pub fn precompiled(&self) -> &[PrecompiledLibrary] {
+        &self.precompiled
+    }
+
+    /// Links the precompiled libraries into the target of the app. The property is appended
+    /// directly, because `target_link_libraries` cannot mix the signatures used by the
+    /// generated CMake files of the different targets.
+    fn cmake_precompiled(&self, variant: &str) -> String {
+        self.precompiled
+            .iter()
+            .map(|library| {
+                format!(
+                    "\n# precompiled library {name}\nfind_package({name} CONFIG REQUIRED PATHS \"{path}\" NO_DEFAULT_PATH)\nset_property(TARGET ${{LF_MAIN_TARGET}} APPEND PROPERTY LINK_LIBRARIES lingo::{name})\n",
+                    name = library.name,
+                    path = library.config_directory(variant).display()
+                )
+            })
+            .collect()
+    }
+
+    /// copies the artifacts of the libraries into the directory of the executable
+    pub fn install_artifacts(&self, destination: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(destination)?;
+        for artifact in &self.artifacts {
+            let name = artifact
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("invalid artifact {}", artifact.display()))?;
+            if artifact.is_dir() {
+                copy_recursively(artifact, destination.join(name))?;
+            } else {
+                std::fs::copy(artifact, destination.join(name)).map_err(|e| {
+                    anyhow::anyhow!("cannot copy artifact {}: {e}", artifact.display())
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_sources_and_artifacts_reach_the_app() {
+        let file = toml::from_str::<LibraryTargetPropertiesFile>(
+            r#"
+sources = ["native/mqtt.c"]
+artifacts = ["certs/ca.pem"]
+"#,
+        )
+        .unwrap();
+        let library = file.from(Path::new("/unused"));
+        assert_eq!(library.artifacts, vec![PathBuf::from("certs/ca.pem")]);
+
+        let mut app = AppTargetProperties::default();
+        app.merge(&library.resolve_paths(Path::new("/libs/mqtt")))
+            .unwrap();
+
+        assert_eq!(
+            app.artifacts,
+            vec![PathBuf::from("/libs/mqtt/certs/ca.pem")]
+        );
+        let cmake = app.cmake_sources();
+        assert!(cmake.contains(
+            "target_sources(${LF_MAIN_TARGET} PRIVATE\n    \"/libs/mqtt/native/mqtt.c\"\n)"
+        ));
+        assert!(cmake.contains(
+            "target_include_directories(${LF_MAIN_TARGET} PRIVATE\n    \"/libs/mqtt/native\"\n)"
+        ));
+    }
 }
@@ -1,10 +1,28 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use url::Url;
 use versions::{Requirement, Versioning};
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::str::FromStr;
 
+use crate::args::{Platform, TargetLanguage};
 use crate::package::target_properties::LibraryTargetProperties;
+use crate::util::errors::LingoError;
+use crate::GitCloneOptions;
+
+/// CCpp programs are compiled with a C++ compiler and can use C libraries as well
+fn can_use_target(target: TargetLanguage, library: TargetLanguage) -> bool {
+    target == library || (target == TargetLanguage::CCpp && library == TargetLanguage::C)
+}
+
+fn list<T: std::fmt::Debug>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| format!("{value:?}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub enum ProjectSource {
@@ -14,8 +32,20 @@ pub enum ProjectSource {
     TarBall(Url),
     #[serde(rename = "path")]
     Path(PathBuf),
-    //#[serde(rename = "empty")]
-    //Empty,
+    /// package is resolved against a registry index, optionally with a custom index location
+    #[serde(rename = "registry")]
+    Registry(Option<String>),
+}
+
+/// dependencies without any source are resolved against the registry
+fn deserialize_project_source<'de, D>(deserializer: D) -> Result<ProjectSource, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        Option::<ProjectSource>::deserialize(deserializer)?
+            .unwrap_or(ProjectSource::Registry(None)),
+    )
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -38,12 +68,136 @@ pub struct PackageDetails {
         serialize_with = "Requirement::serialize"
     )]
     pub(crate) version: Requirement,
-    #[serde(flatten)]
+    #[serde(flatten, deserialize_with = "deserialize_project_source")]
     pub(crate) mutual_exclusive: ProjectSource,
     #[serde(flatten)]
     pub(crate) git_tag: Option<GitLock>,
     #[serde(skip)]
     pub(crate) git_rev: Option<String>,
+    /// expected sha256 of the archive, only used for tarballs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sha256: Option<String>,
+    /// archive the registry resolved this package to
+    #[serde(skip)]
+    pub(crate) tarball: Option<Url>,
+    /// directory inside the git repository that contains the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) subdir: Option<PathBuf>,
+    /// only fetch the history of the checked out revision, defaults to false
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) shallow: Option<bool>,
+    /// clone the submodules of the git repository, defaults to true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) submodules: Option<bool>,
+    /// the dependency is only used if a feature of the dependant enables it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) optional: bool,
+    /// features of the dependency that are enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) features: Vec<String>,
+    /// enables the default feature of the dependency, defaults to true
+    #[serde(
+        rename = "default-features",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) default_features: Option<bool>,
+}
+
+impl PackageDetails {
+    /// requires versions that are compatible with the given one, e.g. `^1.2.3` for a dependency
+    /// that was added without a version requirement
+    pub fn require_compatible(&mut self, version: &Versioning) -> anyhow::Result<()> {
+        self.version = Requirement::from_str(&format!("^{version}"))?;
+        Ok(())
+    }
+
+    /// how the git repository of this package is cloned
+    pub fn git_clone_options(&self) -> GitCloneOptions {
+        GitCloneOptions {
+            shallow: self.shallow.unwrap_or(false),
+            submodules: self.submodules.unwrap_or(true),
+        }
+    }
+
+    /// identifies the location this package is fetched from
+    pub fn source_id(&self) -> String {
+        let source = match &self.mutual_exclusive {
+            ProjectSource::Git(url) => format!("git+{}", url),
+            ProjectSource::TarBall(url) => format!("tar+{}", url),
+            ProjectSource::Path(path) => format!("path+{}", path.display()),
+            ProjectSource::Registry(location) => match &self.tarball {
+                Some(url) => format!("registry+{}", url),
+                None => format!("registry+{}", location.clone().unwrap_or_default()),
+            },
+        };
+
+        let mut parameters = Vec::new();
+        match &self.git_tag {
+            Some(GitLock::Tag(tag)) => parameters.push(format!("tag={}", tag)),
+            Some(GitLock::Branch(branch)) => parameters.push(format!("branch={}", branch)),
+            Some(GitLock::Rev(rev)) => parameters.push(format!("rev={}", rev)),
+            None => {}
+        }
+        parameters.extend(self.content_parameters());
+
+        if parameters.is_empty() {
+            source
+        } else {
+            format!("{}?{}", source, parameters.join("&"))
+        }
+    }
+
+    /// options that change which files of a git repository end up in the package
+    pub(crate) fn content_parameters(&self) -> Vec<String> {
+        let mut parameters = Vec::new();
+        if let Some(subdir) = &self.subdir {
+            parameters.push(format!("subdir={}", subdir.display()));
+        }
+        if !self.git_clone_options().submodules {
+            parameters.push("submodules=false".to_string());
+        }
+        parameters
+    }
+}
+
+/// Source that replaces the source of a dependency everywhere in the dependency tree,
+/// configured in the `[patch]` table
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct PatchDetails {
+    #[serde(flatten)]
+    pub(crate) mutual_exclusive: ProjectSource,
+    #[serde(flatten)]
+    pub(crate) git_tag: Option<GitLock>,
+    /// expected sha256 of the archive, only used for tarballs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) subdir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) shallow: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) submodules: Option<bool>,
+}
+
+impl PatchDetails {
+    /// the package fetched from the patched source, the version requirement stays the same
+    pub(crate) fn apply(&self, package: &PackageDetails) -> PackageDetails {
+        PackageDetails {
+            version: package.version.clone(),
+            mutual_exclusive: self.mutual_exclusive.clone(),
+            git_tag: self.git_tag.clone(),
+            git_rev: None,
+            sha256: self.sha256.clone(),
+            tarball: None,
+            subdir: self.subdir.clone(),
+            shallow: self.shallow,
+            submodules: self.submodules,
+            optional: package.optional,
+            features: package.features.clone(),
+            default_features: package.default_features,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -64,9 +218,23 @@ pub struct DependencyTreeNode {
     pub(crate) dependencies: Vec<DependencyTreeNode>,
     /// required dependencies to build this package
     pub(crate) properties: LibraryTargetProperties,
+    /// target languages the library can be used from
+    pub(crate) targets: Vec<TargetLanguage>,
+    /// platforms the library can be used on, empty if it works on every platform
+    pub(crate) platforms: Vec<Platform>,
+    /// features of the library that are enabled
+    pub(crate) features: Vec<String>,
+    /// requirements imposed on this package together with the package that imposed them
+    pub(crate) required_by: Vec<(String, Requirement)>,
 }
 
 impl DependencyTreeNode {
+    /// the name under which the package was requested by its dependants
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
     pub fn shallow_clone(&self) -> Self {
         Self {
             name: self.name.clone(),
@@ -76,8 +244,84 @@ impl DependencyTreeNode {
             include_path: self.include_path.clone(),
             hash: self.hash.clone(),
             dependencies: Vec::new(),
-            properties: Default::default(),
+            properties: self.properties.clone(),
+            targets: self.targets.clone(),
+            platforms: self.platforms.clone(),
+            features: self.features.clone(),
+            required_by: self.required_by.clone(),
+        }
+    }
+
+    /// Builds the tree below the package `name` from the selected packages and the edges
+    /// between them. `path` contains the packages from the root to this package and is used
+    /// to detect cycles.
+    pub(crate) fn build(
+        name: &str,
+        selected: &BTreeMap<String, DependencyTreeNode>,
+        edges: &BTreeMap<String, Vec<String>>,
+        path: &mut Vec<String>,
+    ) -> anyhow::Result<DependencyTreeNode> {
+        if let Some(position) = path.iter().position(|package| package == name) {
+            let mut cycle = path[position..].to_vec();
+            cycle.push(name.to_string());
+            return Err(LingoError::DependencyCycle(cycle).into());
+        }
+
+        let mut node = selected
+            .get(name)
+            .ok_or(LingoError::UnknownDependencyNames(vec![name.to_string()]))?
+            .shallow_clone();
+
+        path.push(name.to_string());
+        for dependency in edges.get(name).into_iter().flatten() {
+            node.dependencies
+                .push(Self::build(dependency, selected, edges, path)?);
         }
+        path.pop();
+
+        Ok(node)
+    }
+
+    /// Checks that a package written in one of the `targets` for one of the `platforms` can use
+    /// this library, an empty list of platforms stands for every platform.
+    pub(crate) fn check_usable_by(
+        &self,
+        dependant: &str,
+        targets: &[TargetLanguage],
+        platforms: &[Platform],
+    ) -> anyhow::Result<()> {
+        let incompatible = |reason: String| {
+            LingoError::IncompatibleLibrary(dependant.to_string(), self.name.clone(), reason)
+        };
+
+        if !targets.iter().any(|target| {
+            self.targets
+                .iter()
+                .any(|library| can_use_target(*target, *library))
+        }) {
+            return Err(incompatible(format!(
+                "it supports the targets {} but not {}",
+                list(&self.targets),
+                list(targets)
+            ))
+            .into());
+        }
+
+        if !platforms.is_empty()
+            && !self.platforms.is_empty()
+            && !platforms
+                .iter()
+                .any(|platform| self.platforms.contains(platform))
+        {
+            return Err(incompatible(format!(
+                "it supports the platforms {} but not {}",
+                list(&self.platforms),
+                list(platforms)
+            ))
+            .into());
+        }
+
+        Ok(())
     }
 
     pub fn aggregate(&self) -> Vec<DependencyTreeNode> {
@@ -91,3 +335,151 @@ impl DependencyTreeNode {
         aggregator
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::lock::DependencyLock;
+    use crate::package::AppFile;
+    use std::path::Path;
+
+    fn node(name: &str) -> DependencyTreeNode {
+        DependencyTreeNode {
+            name: name.to_string(),
+            version: Versioning::new("1.0.0").unwrap(),
+            package: PackageDetails {
+                version: Requirement::default(),
+                mutual_exclusive: ProjectSource::Path(PathBuf::new()),
+                git_tag: None,
+                git_rev: None,
+                sha256: None,
+                tarball: None,
+                subdir: None,
+                shallow: None,
+                submodules: None,
+                optional: false,
+                features: vec![],
+                default_features: None,
+            },
+            location: PathBuf::new(),
+            include_path: PathBuf::new(),
+            hash: String::new(),
+            dependencies: vec![],
+            properties: LibraryTargetProperties::default(),
+            targets: vec![TargetLanguage::C],
+            platforms: vec![],
+            features: vec![],
+            required_by: vec![],
+        }
+    }
+
+    fn graph(
+        edges: &[(&str, &[&str])],
+    ) -> (
+        BTreeMap<String, DependencyTreeNode>,
+        BTreeMap<String, Vec<String>>,
+    ) {
+        let selected = edges
+            .iter()
+            .map(|(name, _)| (name.to_string(), node(name)))
+            .collect();
+        let edges = edges
+            .iter()
+            .map(|(name, dependencies)| {
+                (
+                    name.to_string(),
+                    dependencies.iter().map(|dep| dep.to_string()).collect(),
+                )
+            })
+            .collect();
+        (selected, edges)
+    }
+
+    #[test]
+    fn detects_dependency_cycles() {
+        let (selected, edges) = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+
+        let error = DependencyTreeNode::build("a", &selected, &edges, &mut vec![]).unwrap_err();
+        match error.downcast_ref::<LingoError>() {
+            Some(LingoError::DependencyCycle(cycle)) => assert_eq!(cycle, &["a", "b", "c", "a"]),
+            _ => panic!("expected a dependency cycle, got {error}"),
+        }
+    }
+
+    #[test]
+    fn shared_dependencies_are_not_a_cycle() {
+        // a depends on d through b and through c
+        let (selected, edges) =
+            graph(&[("a", &["b", "c"]), ("b", &["d"]), ("c", &["d"]), ("d", &[])]);
+
+        let tree = DependencyTreeNode::build("a", &selected, &edges, &mut vec![]).unwrap();
+        let names = tree
+            .aggregate()
+            .iter()
+            .map(|node| node.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b", "d", "c", "d"]);
+    }
+
+    #[test]
+    fn checks_targets_and_platforms() {
+        let mut c_library = node("clib");
+        c_library.platforms = vec![Platform::Native, Platform::Zephyr];
+
+        // CCpp is compiled with a C++ compiler and can use C libraries, but not the other way
+        let usable = |targets: &[TargetLanguage], platforms: &[Platform]| {
+            c_library
+                .check_usable_by("the app", targets, platforms)
+                .is_ok()
+        };
+        assert!(usable(&[TargetLanguage::C], &[Platform::Native]));
+        assert!(usable(&[TargetLanguage::CCpp], &[Platform::Native]));
+        assert!(!usable(&[TargetLanguage::Cpp], &[Platform::Native]));
+
+        // one of the platforms of the library is enough, no platforms stand for all of them
+        assert!(usable(&[TargetLanguage::C], &[Platform::Zephyr]));
+        assert!(usable(
+            &[TargetLanguage::C],
+            &[Platform::RP2040, Platform::Zephyr]
+        ));
+        assert!(!usable(&[TargetLanguage::C], &[Platform::RP2040]));
+        assert!(usable(&[TargetLanguage::C], &[]));
+
+        let mut cpp_library = node("cpplib");
+        cpp_library.targets = vec![TargetLanguage::CCpp, TargetLanguage::Cpp];
+        assert!(cpp_library
+            .check_usable_by("the app", &[TargetLanguage::Cpp], &[Platform::RIOT])
+            .is_ok());
+        assert!(cpp_library
+            .check_usable_by("the app", &[TargetLanguage::C], &[Platform::Native])
+            .is_err());
+    }
+
+    #[test]
+    fn checks_every_library_of_the_app() {
+        let mut zephyr_library = node("zephyr");
+        zephyr_library.platforms = vec![Platform::Zephyr];
+        let lock = DependencyLock::create(vec![node("clib"), zephyr_library], &BTreeMap::new());
+
+        let app = |target: TargetLanguage, platform: Platform| {
+            AppFile {
+                name: Some("app".to_string()),
+                main: Some("src/Main.lf".into()),
+                target,
+                platform: Some(platform),
+                properties: Default::default(),
+                expect: None,
+                tags: vec![],
+            }
+            .convert("package", Path::new("/package"))
+        };
+        assert!(lock
+            .check_compatibility(&app(TargetLanguage::CCpp, Platform::Zephyr))
+            .is_ok());
+
+        let error = lock
+            .check_compatibility(&app(TargetLanguage::C, Platform::Native))
+            .unwrap_err();
+        assert!(error.to_string().contains("zephyr"), "{error}");
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::util::copy_recursively;
+
+/// Archive formats that can be unpacked
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarXz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// determines the archive format from the file name
+    pub fn from_file_name(name: &str) -> Option<ArchiveFormat> {
+        let name = name.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(ArchiveFormat::TarXz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Unpacks the archive into the destination. If all the contents of the archive are inside
+/// a single top-level directory, this directory is stripped.
+pub fn unpack(archive: &Path, format: ArchiveFormat, destination: &Path) -> anyhow::Result<()> {
+    let unpack_dir = tempfile::tempdir()?;
+    let reader = BufReader::new(File::open(archive)?);
+
+    match format {
+        ArchiveFormat::Tar => tar::Archive::new(reader).unpack(unpack_dir.path())?,
+        ArchiveFormat::TarGz => {
+            tar::Archive::new(flate2::read::GzDecoder::new(reader)).unpack(unpack_dir.path())?
+        }
+        ArchiveFormat::TarXz => {
+            let mut decompressed = Vec::new();
+            lzma_rs::xz_decompress(&mut BufReader::new(reader), &mut decompressed)?;
+            tar::Archive::new(decompressed.as_slice()).unpack(unpack_dir.path())?
+        }
+        ArchiveFormat::Zip => zip::ZipArchive::new(reader)?.extract(unpack_dir.path())?,
+    }
+
+    let entries = fs::read_dir(unpack_dir.path())?.collect::<Result<Vec<_>, _>>()?;
+    let root = match entries.as_slice() {
+        [single] if single.file_type()?.is_dir() => single.path(),
+        _ => unpack_dir.path().to_path_buf(),
+    };
+
+    copy_recursively(root, destination)?;
+    Ok(())
+}
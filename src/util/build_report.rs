@@ -0,0 +1,65 @@
+use serde_derive::{Deserialize, Serialize};
+
+use std::fs;
+use std::path::Path;
+
+use crate::util::errors::LingoError;
+
+/// File inside the build directory that contains the results of the last run of `command`, so
+/// `--failed` of one command is not confused by the results of another.
+pub fn build_report_file(command: &str) -> String {
+    format!("results-{command}.json")
+}
+
+/// Result of a single app in the last build, run or test
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AppReport {
+    pub name: String,
+    pub success: bool,
+
+    /// the build step that failed, e.g. `compile` or `run`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Results of all apps of the last build, used by `--failed` to build only the failed apps again
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    pub apps: Vec<AppReport>,
+}
+
+impl BuildReport {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let text =
+            fs::read_to_string(path).map_err(|_| LingoError::NoBuildReport(path.to_path_buf()))?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// names of the apps that failed
+    pub fn failed(&self) -> impl Iterator<Item = &str> {
+        self.apps
+            .iter()
+            .filter(|app| !app.success)
+            .map(|app| app.name.as_str())
+    }
+
+    /// marks an app as failed in the given step, e.g. after it was built but failed to run
+    pub fn fail(&mut self, name: &str, step: &str, error: String) {
+        if let Some(app) = self.apps.iter_mut().find(|app| app.name == name) {
+            app.success = false;
+            app.step = Some(step.to_string());
+            app.error = Some(error);
+        }
+    }
+}
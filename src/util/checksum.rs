@@ -0,0 +1,157 @@
+use sha2::{Digest, Sha256};
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Entries that never belong to the content of a package, version control metadata anywhere
+/// and build outputs at the top level of the package.
+const EXCLUDED_EVERYWHERE: &[&str] = &[".git"];
+const EXCLUDED_AT_ROOT: &[&str] = &["build", "src-gen", "fed-gen", "bin"];
+
+/// Computes a SHA-256 hash over the content of the directory. The hash only depends on the
+/// relative paths, the file contents and symlink targets, not on where the directory is
+/// located or in which order the file system lists the entries.
+pub fn checksum_dir(root: &Path) -> io::Result<String> {
+    let mut entries = Vec::new();
+    collect(root, Path::new(""), &mut entries)?;
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+    for relative in entries {
+        let path = root.join(&relative);
+        let file_type = path.symlink_metadata()?.file_type();
+
+        // paths are hashed with forward slashes, so the hash is the same on every platform
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if file_type.is_symlink() {
+            update(&mut hasher, b'l', name.as_bytes());
+            let target = fs::read_link(&path)?;
+            update(&mut hasher, b't', target.to_string_lossy().as_bytes());
+        } else if file_type.is_dir() {
+            update(&mut hasher, b'd', name.as_bytes());
+        } else if file_type.is_file() {
+            update(&mut hasher, b'f', name.as_bytes());
+            hash_file(&mut hasher, &path)?;
+        }
+        // other file types like sockets or devices are not part of a package
+    }
+
+    Ok(hex(&hasher.finalize()))
+}
+
+/// computes the hex encoded sha256 of the given file
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    hash_reader(&mut hasher, File::open(path)?)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// lower case hex encoding of a digest
+pub fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// feeds everything the reader returns into the hasher
+fn hash_reader(hasher: &mut Sha256, reader: impl Read) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// collects the paths relative to the root of all entries that are part of the package
+fn collect(root: &Path, relative: &Path, entries: &mut Vec<PathBuf>) -> io::Result<()> {
+    for child in fs::read_dir(root.join(relative))? {
+        let child = child?;
+        let name = child.file_name();
+        let name = name.to_string_lossy();
+        if EXCLUDED_EVERYWHERE.contains(&name.as_ref())
+            || (relative.as_os_str().is_empty() && EXCLUDED_AT_ROOT.contains(&name.as_ref()))
+        {
+            continue;
+        }
+
+        let child_relative = relative.join(child.file_name());
+        if child.file_type()?.is_dir() {
+            collect(root, &child_relative, entries)?;
+        }
+        entries.push(child_relative);
+    }
+    Ok(())
+}
+
+/// hashes a tagged, length prefixed value so that different entries cannot collide
+fn update(hasher: &mut Sha256, kind: u8, value: &[u8]) {
+    hasher.update([kind]);
+    hasher.update((value.len() as u64).to_le_bytes());
+    hasher.update(value);
+}
+
+fn hash_file(hasher: &mut Sha256, path: &Path) -> io::Result<()> {
+    let file = File::open(path)?;
+    hasher.update(file.metadata()?.len().to_le_bytes());
+    hash_reader(hasher, file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(root: &Path) {
+        fs::create_dir_all(root.join("src/lib")).unwrap();
+        fs::write(root.join("Lingo.toml"), "[package]\n").unwrap();
+        fs::write(root.join("src/lib/Lib.lf"), "reactor Lib {}\n").unwrap();
+    }
+
+    #[test]
+    fn independent_of_location_and_excluded_entries() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        package(a.path());
+        package(b.path());
+        fs::create_dir_all(b.path().join(".git")).unwrap();
+        fs::write(b.path().join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::create_dir_all(b.path().join("build")).unwrap();
+        fs::write(b.path().join("build/output"), "binary").unwrap();
+
+        assert_eq!(
+            checksum_dir(a.path()).unwrap(),
+            checksum_dir(b.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn depends_on_content_and_names() {
+        let a = tempfile::tempdir().unwrap();
+        package(a.path());
+        let original = checksum_dir(a.path()).unwrap();
+
+        fs::write(a.path().join("src/lib/Lib.lf"), "reactor Lib { }\n").unwrap();
+        let changed_content = checksum_dir(a.path()).unwrap();
+        assert_ne!(original, changed_content);
+
+        fs::rename(
+            a.path().join("src/lib/Lib.lf"),
+            a.path().join("src/lib/Other.lf"),
+        )
+        .unwrap();
+        let renamed = checksum_dir(a.path()).unwrap();
+        assert_ne!(changed_content, renamed);
+
+        // only build outputs at the top level are left out
+        fs::create_dir_all(a.path().join("src/bin")).unwrap();
+        fs::write(a.path().join("src/bin/Tool.lf"), "reactor Tool {}\n").unwrap();
+        assert_ne!(renamed, checksum_dir(a.path()).unwrap());
+    }
+}
@@ -1,5 +1,7 @@
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::util::errors::{BuildResult, LingoError};
 use crossbeam::thread;
@@ -69,6 +71,85 @@ pub fn run_and_capture(command: &mut Command) -> io::Result<(ExitStatus, Vec<u8>
     .expect("stdout/stderr thread panicked")
 }
 
+/// Runs the command with its output captured. The command is killed if it is still running
+/// after the timeout, in this case no exit status is returned.
+pub fn run_with_timeout(
+    command: &mut Command,
+    timeout: Duration,
+) -> io::Result<(Option<ExitStatus>, Vec<u8>, Vec<u8>)> {
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    let mut child = command.spawn()?;
+    // These expects should be guaranteed to be ok because we used piped().
+    let child_stdout = child.stdout.take().expect("logic error getting stdout");
+    let child_stderr = child.stderr.take().expect("logic error getting stderr");
+    log::info!("Running {:?}", command);
+
+    // processes started by the command may keep the pipes open after it was killed, so the
+    // output is collected by detached threads and whatever arrived until then is returned
+    let stdout_log = collect_output(child_stdout);
+    let stderr_log = collect_output(child_stderr);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    let stdout_log = match status {
+        Some(_) => stdout_log.join().expect("stdout thread panicked"),
+        None => stdout_log.snapshot(),
+    };
+    let stderr_log = match status {
+        Some(_) => stderr_log.join().expect("stderr thread panicked"),
+        None => stderr_log.snapshot(),
+    };
+    Ok((status, stdout_log, stderr_log))
+}
+
+/// output of a child process that is read by a background thread
+struct OutputCollector {
+    output: Arc<Mutex<Vec<u8>>>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+fn collect_output(mut pipe: impl Read + Send + 'static) -> OutputCollector {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let shared = output.clone();
+    let thread = std::thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        while let Ok(read) = pipe.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            shared.lock().unwrap().extend_from_slice(&buffer[..read]);
+        }
+    });
+    OutputCollector { output, thread }
+}
+
+impl OutputCollector {
+    /// waits until the pipe was closed
+    fn join(self) -> std::thread::Result<Vec<u8>> {
+        let OutputCollector { output, thread } = self;
+        thread.join()?;
+        let output = output.lock().unwrap().clone();
+        Ok(output)
+    }
+
+    /// the output that has been read so far
+    fn snapshot(&self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+}
+
 pub fn execute_command_to_build_result(mut command: Command) -> BuildResult {
     match run_and_capture(&mut command) {
         Err(e) => {
@@ -18,6 +18,26 @@ pub enum LingoError {
     InvalidMainReactor,
     NoLibraryInLingoToml(String),
     LingoVersionMismatch(String),
+    UnknownDependencyNames(Vec<String>),
+    UnsupportedArchiveFormat(String),
+    ArchiveChecksumMismatch(String, String, String),
+    NoRegistryConfigured,
+    InvalidRegistry(String),
+    NotInRegistry(String),
+    UnsatisfiableRequirements(String),
+    DependencyCycle(Vec<String>),
+    NoCacheLocation,
+    LockFileOutdated,
+    OfflineFetch(String),
+    ChecksumMismatch(String, String, String),
+    UnsupportedLockFileVersion(i64),
+    MissingGitSubdirectory(String, String),
+    IncompatibleLibrary(String, String, String),
+    UnknownFeature(String, String),
+    TestsFailed(usize, usize),
+    UnexpectedOutput(String, String),
+    BuildAborted,
+    NoBuildReport(PathBuf),
 }
 
 impl Display for LingoError {
@@ -56,6 +76,101 @@ impl Display for LingoError {
                     "Version specified in Lingo.toml doesn't match the version in the location {message}"
                 )
             }
+            LingoError::UnknownDependencyNames(names) => {
+                write!(f, "Unknown dependency names: {}", names.join(", "))
+            }
+            LingoError::UnsupportedArchiveFormat(url) => {
+                write!(
+                    f,
+                    "Cannot unpack {url}, supported are .tar, .tar.gz, .tar.xz and .zip archives"
+                )
+            }
+            LingoError::ArchiveChecksumMismatch(url, expected, actual) => {
+                write!(
+                    f,
+                    "Checksum of {url} doesn't match, expected sha256 {expected} got {actual}"
+                )
+            }
+            LingoError::NoRegistryConfigured => {
+                write!(
+                    f,
+                    "A dependency without source requires a registry, set LINGO_REGISTRY or add registry = \"<index>\" to the dependency"
+                )
+            }
+            LingoError::InvalidRegistry(location) => {
+                write!(f, "Not a valid registry location {location}")
+            }
+            LingoError::NotInRegistry(name) => {
+                write!(f, "Package {name} cannot be found in the registry")
+            }
+            LingoError::LockFileOutdated => {
+                write!(
+                    f,
+                    "Lingo.lock is missing or needs to be updated, but it is locked by --locked or --frozen"
+                )
+            }
+            LingoError::MissingGitSubdirectory(url, subdir) => {
+                write!(
+                    f,
+                    "The git repository {url} does not contain the directory {subdir}"
+                )
+            }
+            LingoError::IncompatibleLibrary(dependant, library, reason) => {
+                write!(f, "{dependant} cannot use the library {library}, {reason}")
+            }
+            LingoError::UnknownFeature(package, feature) => {
+                write!(f, "The package {package} has no feature {feature}")
+            }
+            LingoError::TestsFailed(failed, total) => {
+                write!(f, "{failed} of {total} tests failed")
+            }
+            LingoError::UnexpectedOutput(app, mismatches) => {
+                write!(f, "{app} did not print the expected output\n{mismatches}")
+            }
+            LingoError::BuildAborted => {
+                write!(
+                    f,
+                    "Not built because another app failed, pass --keep-going to build it anyway"
+                )
+            }
+            LingoError::NoBuildReport(path) => {
+                write!(
+                    f,
+                    "There are no results of an earlier build in {}, build all apps first",
+                    path.display()
+                )
+            }
+            LingoError::UnsupportedLockFileVersion(version) => {
+                write!(
+                    f,
+                    "Lingo.lock has format version {version} which is not supported by this version of lingo"
+                )
+            }
+            LingoError::ChecksumMismatch(name, expected, actual) => {
+                write!(
+                    f,
+                    "Checksum of {name} does not match Lingo.lock, expected {expected} but got {actual}. \
+                     Run `lingo update {name}` if the change is intended or pass --ignore-checksums"
+                )
+            }
+            LingoError::OfflineFetch(url) => {
+                write!(
+                    f,
+                    "Cannot fetch {url} in offline mode, it is neither cached nor available locally"
+                )
+            }
+            LingoError::NoCacheLocation => {
+                write!(
+                    f,
+                    "Cannot determine the location of the cache, set the LINGO_CACHE environment variable"
+                )
+            }
+            LingoError::DependencyCycle(cycle) => {
+                write!(f, "Dependency cycle detected: {}", cycle.join(" -> "))
+            }
+            LingoError::UnsatisfiableRequirements(explanation) => {
+                write!(f, "Dependency resolution failed, {explanation}")
+            }
         }
     }
 }
@@ -0,0 +1,195 @@
+use colored::Colorize;
+use regex::RegexBuilder;
+
+use std::fs;
+use std::path::Path;
+
+use crate::package::ExpectedOutput;
+
+/// Compares the captured output of an app with the output it is expected to print. Returns a
+/// description of every difference, or `None` if the output is as expected. With `bless` the
+/// expected output files are overwritten with the captured output instead.
+pub fn check_output(
+    expected: &ExpectedOutput,
+    stdout: &[u8],
+    stderr: &[u8],
+    bless: bool,
+) -> anyhow::Result<Option<String>> {
+    let mut mismatches = String::new();
+    let streams = [
+        ("stdout", &expected.stdout, &expected.stdout_regex, stdout),
+        ("stderr", &expected.stderr, &expected.stderr_regex, stderr),
+    ];
+
+    for (stream, golden_file, patterns, actual) in streams {
+        let actual = String::from_utf8_lossy(actual);
+        if let Some(golden_file) = golden_file {
+            if bless {
+                bless_file(golden_file, &actual)?;
+            } else {
+                let golden = fs::read_to_string(golden_file).map_err(|e| {
+                    anyhow::anyhow!(
+                        "cannot read the expected {stream} {}: {e}, run with --bless to create it",
+                        golden_file.display()
+                    )
+                })?;
+                if golden != actual {
+                    mismatches += &format!(
+                        "{stream} differs from {}\n{}",
+                        golden_file.display(),
+                        diff(&golden, &actual)
+                    );
+                }
+            }
+        }
+
+        for pattern in patterns {
+            let regex = RegexBuilder::new(pattern).multi_line(true).build()?;
+            if !regex.is_match(&actual) {
+                mismatches += &format!("{stream} does not match the regex {pattern}\n");
+            }
+        }
+    }
+
+    Ok((!mismatches.is_empty()).then_some(mismatches))
+}
+
+fn bless_file(path: &Path, output: &str) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, output)?;
+    Ok(())
+}
+
+/// unchanged lines that are shown around a change
+const CONTEXT_LINES: usize = 2;
+
+/// longer outputs are not compared line by line, the quadratic comparison would take too long
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line based diff between the expected and the actual output, removed lines are prefixed with
+/// `-` and added lines with `+`. Unchanged lines far away from a change are left out.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+
+    // the common start and end are skipped, so only the changed middle part is compared
+    let prefix = expected
+        .iter()
+        .zip(&actual)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed = &expected[prefix..expected.len() - suffix];
+    let added = &actual[prefix..actual.len() - suffix];
+
+    let mut lines = expected[..prefix]
+        .iter()
+        .map(|line| Line::Same(line))
+        .collect::<Vec<_>>();
+    lines.extend(compare(removed, added));
+    lines.extend(
+        expected[expected.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let near_change = |index: usize| {
+        changed
+            .iter()
+            .any(|&change| index + CONTEXT_LINES >= change && index <= change + CONTEXT_LINES)
+    };
+
+    let mut output = String::new();
+    let mut skipped = false;
+    for (index, line) in lines.iter().enumerate() {
+        match line {
+            Line::Same(_) if !near_change(index) => {
+                if !skipped {
+                    output += "   ...\n";
+                    skipped = true;
+                }
+                continue;
+            }
+            Line::Same(text) => output += &format!("  {text}\n"),
+            Line::Removed(text) => output += &format!("{}\n", format!("- {text}").red()),
+            Line::Added(text) => output += &format!("{}\n", format!("+ {text}").green()),
+        }
+        skipped = false;
+    }
+    output
+}
+
+/// longest common subsequence of the lines, everything else was removed or added
+fn compare<'a>(removed: &[&'a str], added: &[&'a str]) -> Vec<Line<'a>> {
+    if removed.len() * added.len() > MAX_DIFF_CELLS {
+        return removed
+            .iter()
+            .map(|line| Line::Removed(line))
+            .chain(added.iter().map(|line| Line::Added(line)))
+            .collect();
+    }
+
+    // common[i][j] is the length of the longest common subsequence of removed[i..] and added[j..]
+    let mut common = vec![vec![0usize; added.len() + 1]; removed.len() + 1];
+    for i in (0..removed.len()).rev() {
+        for j in (0..added.len()).rev() {
+            common[i][j] = if removed[i] == added[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < removed.len() || j < added.len() {
+        if i < removed.len() && j < added.len() && removed[i] == added[j] {
+            lines.push(Line::Same(removed[i]));
+            i += 1;
+            j += 1;
+        } else if j == added.len() || (i < removed.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(Line::Removed(removed[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(added[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_changed_lines() {
+        colored::control::set_override(false);
+        let expected = "start\n1\n2\n3\n4\n5\n6\n7\nend\n";
+        let actual = "start\n1\n2\n3\n4\n5\nsix\n7\nend\nextra\n";
+        assert_eq!(
+            diff(expected, actual),
+            "   ...\n  4\n  5\n- 6\n+ six\n  7\n  end\n+ extra\n"
+        );
+    }
+}
@@ -1,7 +1,13 @@
 pub mod analyzer;
+pub mod archive;
+pub mod build_report;
+pub mod checksum;
 mod command_line;
 pub mod errors;
-pub mod sha1dir;
+pub mod golden;
+pub mod selection;
+pub mod testing;
+pub mod time;
 
 pub use command_line::*;
 use std::path::{Path, PathBuf};
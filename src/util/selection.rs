@@ -0,0 +1,154 @@
+use regex::Regex;
+
+use crate::args::BuildArgs;
+use crate::package::App;
+use crate::util::build_report::BuildReport;
+use crate::util::errors::LingoError;
+
+/// Keeps the apps selected on the command line by name, glob, regex and tag, without the
+/// excluded apps. With `--shard N/M` the selected apps are sorted by name and every M-th app
+/// starting at the N-th is kept, so every shard gets the same share of the apps. With
+/// `--failed` only the apps that failed according to the report of the last build are kept.
+pub fn select_apps(
+    mut apps: Vec<App>,
+    args: &BuildArgs,
+    last_build: Option<&BuildReport>,
+) -> anyhow::Result<Vec<App>> {
+    // names without wildcards have to exist, a typo would otherwise silently build nothing
+    let unknown_names = args
+        .apps
+        .iter()
+        .chain(&args.exclude)
+        .filter(|&name| !is_glob(name) && !apps.iter().any(|app| &app.name == name))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !unknown_names.is_empty() {
+        return Err(LingoError::UnknownAppNames(unknown_names).into());
+    }
+
+    let mut included = args
+        .apps
+        .iter()
+        .map(|pattern| glob(pattern))
+        .collect::<Result<Vec<_>, _>>()?;
+    for regex in &args.regex {
+        included.push(Regex::new(regex)?);
+    }
+    let excluded = args
+        .exclude
+        .iter()
+        .map(|pattern| glob(pattern))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    apps.retain(|app| {
+        let named = included.is_empty() || included.iter().any(|regex| regex.is_match(&app.name));
+        let tagged = args.tags.is_empty() || app.tags.iter().any(|tag| args.tags.contains(tag));
+        named && tagged && !excluded.iter().any(|regex| regex.is_match(&app.name))
+    });
+    if let Some(last_build) = last_build {
+        apps.retain(|app| last_build.failed().any(|name| name == app.name));
+    }
+
+    if let Some(shard) = args.shard {
+        apps.sort_by(|a, b| a.name.cmp(&b.name));
+        apps = apps
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| index % shard.count == shard.index - 1)
+            .map(|(_, app)| app)
+            .collect();
+    }
+    Ok(apps)
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Converts a glob into a regex matching the whole name. Supported are `*`, `?` and character
+/// classes like `[a-c]` or `[!a-c]`.
+fn glob(pattern: &str) -> Result<Regex, regex::Error> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex += ".*",
+            '?' => regex += ".",
+            '[' => {
+                regex += "[";
+                let mut first = true;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => break,
+                        '!' if first => regex += "^",
+                        '\\' | '[' | '&' | '~' => regex += &regex::escape(&c.to_string()),
+                        c => regex.push(c),
+                    }
+                    first = false;
+                }
+                regex += "]";
+            }
+            c => regex += &regex::escape(&c.to_string()),
+        }
+    }
+    regex += "$";
+    Regex::new(&regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Command, CommandLineArgs};
+    use crate::package::AppFile;
+    use crate::util::build_report::AppReport;
+    use clap::Parser;
+    use std::path::Path;
+
+    fn app(name: &str, tags: &[&str]) -> App {
+        let file = toml::from_str::<AppFile>(&format!(
+            "name = \"{name}\"\ntarget = \"Cpp\"\ntags = {tags:?}\n[properties]\n"
+        ))
+        .unwrap();
+        file.convert("tests", Path::new("/tmp"))
+    }
+
+    fn select(args: &str) -> Vec<String> {
+        let apps = vec![
+            app("TimerA", &["timing"]),
+            app("TimerB", &["timing", "slow"]),
+            app("Deadline", &["timing"]),
+            app("Hello", &[]),
+            app("Count", &[]),
+        ];
+        let args = CommandLineArgs::parse_from(format!("lingo build {args}").split_whitespace());
+        let Command::Build(build) = args.command else {
+            unreachable!()
+        };
+        let last_build = BuildReport {
+            apps: ["TimerA", "Hello"]
+                .map(|name| AppReport {
+                    name: name.to_string(),
+                    success: name == "Hello",
+                    step: None,
+                    error: None,
+                })
+                .to_vec(),
+        };
+        select_apps(apps, &build, build.failed.then_some(&last_build))
+            .unwrap()
+            .into_iter()
+            .map(|app| app.name)
+            .collect()
+    }
+
+    #[test]
+    fn selects_apps() {
+        assert_eq!(select("--apps Timer*,Count"), ["TimerA", "TimerB", "Count"]);
+        assert_eq!(select("--regex ^D|^H"), ["Deadline", "Hello"]);
+        assert_eq!(select("--tag timing --exclude *B"), ["TimerA", "Deadline"]);
+        assert_eq!(select("--apps Timer[!A]"), ["TimerB"]);
+        assert_eq!(select("--shard 1/2"), ["Count", "Hello", "TimerB"]);
+        assert_eq!(select("--shard 2/2"), ["Deadline", "TimerA"]);
+        assert_eq!(select("--failed --apps Timer*"), ["TimerA"]);
+    }
+}
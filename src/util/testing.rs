@@ -0,0 +1,354 @@
+use colored::Colorize;
+
+use std::fmt::{Display, Formatter};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::package::App;
+use crate::util::errors::BuildResult;
+use crate::util::golden::check_output;
+use crate::util::run_with_timeout;
+
+/// how a single test ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TestStatus {
+    Passed,
+    /// the test exited with a non-zero exit code or could not be started
+    Failed(String),
+    /// the test was killed after running for the given time
+    TimedOut(Duration),
+    /// the test did not print the expected output, contains the differences
+    WrongOutput(String),
+    /// the test could not be built, so it never ran
+    BuildFailed(String),
+    /// the test was built but not run, e.g. because only code was generated
+    Skipped,
+}
+
+pub struct TestOutcome {
+    pub name: String,
+    pub status: TestStatus,
+    pub duration: Duration,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl TestOutcome {
+    fn new(name: &str, status: TestStatus) -> Self {
+        TestOutcome {
+            name: name.to_string(),
+            status,
+            duration: Duration::ZERO,
+            stdout: String::new(),
+            stderr: String::new(),
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        !matches!(self.status, TestStatus::Passed | TestStatus::Skipped)
+    }
+}
+
+/// Results of all tests of a package
+pub struct TestReport {
+    /// name of the package the tests belong to
+    pub suite: String,
+    pub outcomes: Vec<TestOutcome>,
+    pub duration: Duration,
+}
+
+/// Runs the tests that were built successfully one after the other, a test fails if it exits
+/// with a non-zero exit code, runs longer than the timeout or does not print the expected
+/// output. Without `run` the tests are only reported as built, with `bless` the expected output
+/// files are updated.
+pub fn run_tests<'a>(
+    suite: &str,
+    builds: impl Iterator<Item = (&'a App, &'a BuildResult)>,
+    timeout: Duration,
+    run: bool,
+    bless: bool,
+) -> TestReport {
+    let start = Instant::now();
+    let mut outcomes = Vec::new();
+    for (app, build) in builds {
+        let outcome = match build {
+            Err(e) => TestOutcome::new(&app.name, TestStatus::BuildFailed(e.to_string())),
+            Ok(()) if !run => TestOutcome::new(&app.name, TestStatus::Skipped),
+            Ok(()) => run_test(app, timeout, bless),
+        };
+        println!("test {} ... {}", outcome.name, outcome.status.label());
+        outcomes.push(outcome);
+    }
+
+    TestReport {
+        suite: suite.to_string(),
+        outcomes,
+        duration: start.elapsed(),
+    }
+}
+
+fn run_test(app: &App, timeout: Duration, bless: bool) -> TestOutcome {
+    let start = Instant::now();
+    let mut command = Command::new(app.executable_path());
+    let (status, stdout, stderr) = match run_with_timeout(&mut command, timeout) {
+        Ok((Some(status), stdout, stderr)) if status.success() => {
+            let checked = match &app.expect {
+                Some(expected) => check_output(expected, &stdout, &stderr, bless),
+                None => Ok(None),
+            };
+            match checked {
+                Ok(None) => (TestStatus::Passed, vec![], vec![]),
+                Ok(Some(mismatches)) => (TestStatus::WrongOutput(mismatches), stdout, stderr),
+                Err(e) => (TestStatus::Failed(e.to_string()), stdout, stderr),
+            }
+        }
+        Ok((Some(status), stdout, stderr)) => (
+            TestStatus::Failed(match status.code() {
+                Some(code) => format!("exit code {code}"),
+                None => status.to_string(),
+            }),
+            stdout,
+            stderr,
+        ),
+        Ok((None, stdout, stderr)) => (TestStatus::TimedOut(timeout), stdout, stderr),
+        Err(e) => (
+            TestStatus::Failed(format!(
+                "cannot run {}: {e}",
+                app.executable_path().display()
+            )),
+            vec![],
+            vec![],
+        ),
+    };
+
+    TestOutcome {
+        name: app.name.clone(),
+        status,
+        duration: start.elapsed(),
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+    }
+}
+
+impl TestStatus {
+    fn label(&self) -> String {
+        match self {
+            TestStatus::Passed => "ok".green().to_string(),
+            TestStatus::Failed(reason) => format!("{} ({reason})", "FAILED".red()),
+            TestStatus::TimedOut(timeout) => {
+                format!("{} (after {}s)", "TIMEOUT".red(), timeout.as_secs())
+            }
+            TestStatus::WrongOutput(_) => format!("{} (wrong output)", "FAILED".red()),
+            TestStatus::BuildFailed(_) => "BUILD FAILED".red().to_string(),
+            TestStatus::Skipped => "skipped".yellow().to_string(),
+        }
+    }
+}
+
+impl Display for TestStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestStatus::Passed => write!(f, "passed"),
+            TestStatus::Failed(reason) => write!(f, "failed with {reason}"),
+            TestStatus::TimedOut(timeout) => write!(f, "timed out after {}s", timeout.as_secs()),
+            TestStatus::WrongOutput(_) => write!(f, "did not print the expected output"),
+            TestStatus::BuildFailed(error) => write!(f, "build failed: {error}"),
+            TestStatus::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+impl TestReport {
+    pub fn total(&self) -> usize {
+        self.outcomes.len()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.failed())
+            .count()
+    }
+
+    fn count(&self, status: impl Fn(&TestStatus) -> bool) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| status(&outcome.status))
+            .count()
+    }
+
+    /// the output of the failed tests followed by the number of passed and failed tests
+    pub fn render_summary(&self) -> String {
+        let mut output = String::new();
+        let failed = self
+            .outcomes
+            .iter()
+            .filter(|outcome| outcome.failed())
+            .collect::<Vec<_>>();
+
+        if !failed.is_empty() {
+            output += "\nfailures:\n";
+            for outcome in &failed {
+                match &outcome.status {
+                    TestStatus::BuildFailed(error) => {
+                        output += &format!("---- {} build ----\n{error}\n", outcome.name)
+                    }
+                    TestStatus::WrongOutput(mismatches) => {
+                        output += &format!("---- {} output ----\n{mismatches}\n", outcome.name)
+                    }
+                    _ => {}
+                }
+                if !outcome.stdout.is_empty() {
+                    output += &format!("---- {} stdout ----\n{}\n", outcome.name, outcome.stdout);
+                }
+                if !outcome.stderr.is_empty() {
+                    output += &format!("---- {} stderr ----\n{}\n", outcome.name, outcome.stderr);
+                }
+            }
+            output += "\nfailures:\n";
+            for outcome in &failed {
+                output += &format!("    {}\n", outcome.name);
+            }
+        }
+
+        let result = if failed.is_empty() {
+            "ok".green()
+        } else {
+            "FAILED".red()
+        };
+        output += &format!(
+            "\ntest result: {result}. {} passed; {} failed; {} skipped; finished in {:.2}s\n",
+            self.count(|status| *status == TestStatus::Passed),
+            failed.len(),
+            self.count(|status| *status == TestStatus::Skipped),
+            self.duration.as_secs_f64()
+        );
+        output
+    }
+
+    /// the report in the JUnit XML format understood by most CI systems, tests that could not
+    /// be built are reported as errors
+    pub fn junit(&self) -> String {
+        let errors = self.count(|status| matches!(status, TestStatus::BuildFailed(_)));
+        let failures = self.failed() - errors;
+        let skipped = self.count(|status| *status == TestStatus::Skipped);
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml += &format!(
+            "<testsuites name=\"lingo\" tests=\"{}\" failures=\"{failures}\" errors=\"{errors}\" skipped=\"{skipped}\" time=\"{:.3}\">\n",
+            self.total(),
+            self.duration.as_secs_f64()
+        );
+        xml += &format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{failures}\" errors=\"{errors}\" skipped=\"{skipped}\" time=\"{:.3}\">\n",
+            escape(&self.suite),
+            self.total(),
+            self.duration.as_secs_f64()
+        );
+        for outcome in &self.outcomes {
+            xml += &format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                escape(&outcome.name),
+                escape(&self.suite),
+                outcome.duration.as_secs_f64()
+            );
+            let element = match &outcome.status {
+                TestStatus::Passed => None,
+                TestStatus::Failed(reason) => {
+                    Some(format!("<failure message=\"{}\"/>", escape(reason)))
+                }
+                TestStatus::TimedOut(timeout) => Some(format!(
+                    "<failure message=\"timed out after {}s\"/>",
+                    timeout.as_secs()
+                )),
+                TestStatus::WrongOutput(mismatches) => Some(format!(
+                    "<failure message=\"wrong output\">{}</failure>",
+                    escape(mismatches)
+                )),
+                TestStatus::BuildFailed(error) => Some(format!(
+                    "<error message=\"build failed\">{}</error>",
+                    escape(error)
+                )),
+                TestStatus::Skipped => Some("<skipped/>".to_string()),
+            };
+            if element.is_none() && outcome.stdout.is_empty() && outcome.stderr.is_empty() {
+                xml += "/>\n";
+                continue;
+            }
+
+            xml += ">\n";
+            if let Some(element) = element {
+                xml += &format!("      {element}\n");
+            }
+            if !outcome.stdout.is_empty() {
+                xml += &format!(
+                    "      <system-out>{}</system-out>\n",
+                    escape(&outcome.stdout)
+                );
+            }
+            if !outcome.stderr.is_empty() {
+                xml += &format!(
+                    "      <system-err>{}</system-err>\n",
+                    escape(&outcome.stderr)
+                );
+            }
+            xml += "    </testcase>\n";
+        }
+        xml += "  </testsuite>\n</testsuites>\n";
+        xml
+    }
+}
+
+/// escapes text for attributes and elements, control characters like the escape sequences of
+/// colored output are not allowed in XML and are left out
+fn escape(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&apos;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_junit_report() {
+        let mut failed = TestOutcome::new("Overflow", TestStatus::Failed("exit code 1".into()));
+        failed.stderr = "\u{1b}[31mexpected <3>\u{1b}[0m".to_string();
+        let report = TestReport {
+            suite: "tests".to_string(),
+            outcomes: vec![
+                TestOutcome::new("Count", TestStatus::Passed),
+                failed,
+                TestOutcome::new("Broken", TestStatus::BuildFailed("lfc failed".into())),
+            ],
+            duration: Duration::from_millis(1500),
+        };
+
+        assert_eq!(
+            report.junit(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="lingo" tests="3" failures="1" errors="1" skipped="0" time="1.500">
+  <testsuite name="tests" tests="3" failures="1" errors="1" skipped="0" time="1.500">
+    <testcase name="Count" classname="tests" time="0.000"/>
+    <testcase name="Overflow" classname="tests" time="0.000">
+      <failure message="exit code 1"/>
+      <system-err>[31mexpected &lt;3&gt;[0m</system-err>
+    </testcase>
+    <testcase name="Broken" classname="tests" time="0.000">
+      <error message="build failed">lfc failed</error>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}